@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use rlox_intermediate::*;
+
+use crate::resolver::{Resolution, Resolutions};
+
+// Annotated types of a top-level function, checked against direct invocations.
+struct Signature {
+    parameter_types: Vec<Option<Spanned<Type>>>,
+    return_type: Option<Spanned<Type>>,
+}
+
+// Type of an expression, which is only enforced if it's derived from annotations, so
+// that unannotated code is never rejected.
+#[derive(Clone, Copy)]
+struct Inferred {
+    kind: Type,
+    annotated: bool,
+}
+
+impl Inferred {
+    fn new(kind: Type, annotated: bool) -> Self {
+        Self { kind, annotated }
+    }
+
+    fn unknown() -> Self {
+        Self::new(Type::Any, false)
+    }
+}
+
+struct Checker<'a> {
+    resolutions: &'a Resolutions,
+    // annotated locals, keyed by spans of their declarations.
+    locals: HashMap<Span, Spanned<Type>>,
+    // annotated globals.
+    globals: HashMap<String, Spanned<Type>>,
+    functions: HashMap<String, Signature>,
+    // annotated return types of enclosing functions, `None` if unannotated.
+    returns: Vec<Option<Spanned<Type>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(resolutions: &'a Resolutions) -> Self {
+        Self {
+            resolutions,
+            locals: HashMap::new(),
+            globals: HashMap::new(),
+            functions: HashMap::new(),
+            returns: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // Globals may be used before they are defined (e.g. in a function), so all of the
+    // top level annotations are collected first.
+    fn define_globals(&mut self, program: &[Declaration]) {
+        for declaration in program {
+            match declaration {
+                Declaration::Function {
+                    name,
+                    parameter_types,
+                    return_type,
+                    ..
+                } => {
+                    let signature = Signature {
+                        parameter_types: parameter_types.clone(),
+                        return_type: return_type.clone(),
+                    };
+                    self.functions.insert(name.deref().clone(), signature);
+                }
+                Declaration::Var {
+                    name,
+                    annotation: Some(annotation),
+                    ..
+                } => {
+                    self.globals
+                        .insert(name.deref().clone(), annotation.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Class { functions, .. } => {
+                for function in functions {
+                    self.check_declaration(function);
+                }
+            }
+            Declaration::Function {
+                name,
+                parameters,
+                parameter_types,
+                return_type,
+                body,
+            } => {
+                let span = name.span.clone();
+                self.check_function(span, parameters, parameter_types, return_type, body)
+            }
+            Declaration::Var {
+                name,
+                annotation,
+                initializer,
+            } => {
+                match (initializer, annotation) {
+                    (Some(initializer), annotation) => {
+                        let actual = self.check_expression(initializer);
+                        if let Some(annotation) = annotation {
+                            self.expect(annotation, actual, initializer.span());
+                        }
+                    }
+                    // variables without initializers are nil.
+                    (None, Some(annotation)) if !admits_nil(annotation) => {
+                        let note = format!("expected {}, found nil as uninitialized", **annotation);
+                        self.mismatch(annotation, note, name.span.clone());
+                    }
+                    _ => {}
+                }
+                if let Some(annotation) = annotation {
+                    self.locals.insert(name.span.clone(), annotation.clone());
+                }
+            }
+            Declaration::Import { .. } => {}
+            Declaration::Statement(statement) => self.check_statement(statement),
+        }
+    }
+
+    // Check a function declared at `span`.
+    fn check_function(
+        &mut self,
+        span: Span,
+        parameters: &[Spanned<String>],
+        parameter_types: &[Option<Spanned<Type>>],
+        return_type: &Option<Spanned<Type>>,
+        body: &Statement,
+    ) {
+        for (parameter, annotation) in parameters.iter().zip(parameter_types) {
+            if let Some(annotation) = annotation {
+                self.locals
+                    .insert(parameter.span.clone(), annotation.clone());
+            }
+        }
+        self.returns.push(return_type.clone());
+        self.check_statement(body);
+        self.returns.pop();
+        // functions return nil by reaching the end of their bodies.
+        if let Some(annotation) = return_type {
+            if !admits_nil(annotation) && completes(body) {
+                let note = format!("expected {}, found nil returned at the end", **annotation);
+                self.mismatch(annotation, note, span);
+            }
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) | Statement::Print(expression) => {
+                self.check_expression(expression);
+            }
+            Statement::For {
+                initializer,
+                condition,
+                incrementer,
+                body,
+            } => {
+                match initializer {
+                    Some(ForLoopInitializer::VarDeclaration(declaration)) => {
+                        self.check_declaration(declaration)
+                    }
+                    Some(ForLoopInitializer::VarInitialization(expression)) => {
+                        self.check_expression(expression);
+                    }
+                    None => {}
+                }
+                for expression in [condition, incrementer].into_iter().flatten() {
+                    self.check_expression(expression);
+                }
+                self.check_statement(body);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.check_expression(condition);
+                self.check_statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.check_statement(otherwise);
+                }
+            }
+            Statement::Return { span, expression } => {
+                let (actual, span) = match expression {
+                    Some(expression) => (self.check_expression(expression), expression.span()),
+                    None => (Inferred::new(Type::Nil, false), span.clone()),
+                };
+                if let Some(Some(annotation)) = self.returns.last().cloned() {
+                    self.expect(&annotation, actual, span);
+                }
+            }
+            Statement::Throw { expression, .. } => {
+                self.check_expression(expression);
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.check_statement(body);
+                if let Some((_, body)) = catch {
+                    self.check_statement(body);
+                }
+                if let Some(finally) = finally {
+                    self.check_statement(finally);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.check_expression(condition);
+                self.check_statement(body);
+            }
+            Statement::Block(declarations) => {
+                for declaration in declarations {
+                    self.check_declaration(declaration);
+                }
+            }
+        }
+    }
+
+    // Check the expression and infer its type, which is `Type::Any` if unknown.
+    fn check_expression(&mut self, expression: &Expression) -> Inferred {
+        match expression {
+            Expression::Assignment { left, right, .. } => {
+                let actual = self.check_expression(right);
+                if let Expression::Literal(Spanned {
+                    value: Literal::Identifier(identifier),
+                    span,
+                }) = left.deref()
+                {
+                    if let Some(annotation) = self.annotation(identifier, span) {
+                        self.expect(&annotation, actual, right.span());
+                    }
+                }
+                actual
+            }
+            Expression::Ternary {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.check_expression(condition);
+                let then = self.check_expression(then);
+                let otherwise = self.check_expression(otherwise);
+                Self::join(then, otherwise)
+            }
+            Expression::CompoundAssignment {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.check_expression(left);
+                let right = self.check_expression(right);
+                self.check_binary_operator(operator, left, right)
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.check_expression(left);
+                // the right operand of property access is a name, rather than a variable.
+                if let BinaryOperator::PropertyAccess = operator.deref() {
+                    return Inferred::unknown();
+                }
+                let right = self.check_expression(right);
+                self.check_binary_operator(operator, left, right)
+            }
+            Expression::Unary {
+                operator,
+                expression,
+            } => {
+                let actual = self.check_expression(expression);
+                match operator.deref() {
+                    UnaryOperator::Not => Inferred::new(Type::Boolean, actual.annotated),
+                    UnaryOperator::Negate => {
+                        self.expect_number(&operator.span, actual, actual.annotated);
+                        Inferred::new(Type::Number, actual.annotated)
+                    }
+                }
+            }
+            Expression::Update {
+                operator,
+                expression,
+                ..
+            } => {
+                let actual = self.check_expression(expression);
+                self.expect_number(&operator.span, actual, actual.annotated);
+                Inferred::new(Type::Number, actual.annotated)
+            }
+            Expression::Invocation {
+                expression,
+                arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| (self.check_expression(argument), argument.span()))
+                    .collect::<Vec<_>>();
+                let callee = self.check_expression(expression);
+                if callee.annotated && !matches!(callee.kind, Type::Any | Type::Function) {
+                    let diagnostic = errors::raise("E0014", expression.span())
+                        .with_notes(vec![format!("found {}", callee.kind)]);
+                    self.diagnostics.push(diagnostic);
+                    return Inferred::unknown();
+                }
+                let Some(signature) = self.signature(expression) else {
+                    return Inferred::unknown();
+                };
+                let parameter_types = signature.parameter_types.clone();
+                let return_type = signature.return_type.clone();
+                // arities are checked by the compiler.
+                for (annotation, (actual, span)) in parameter_types.iter().zip(arguments) {
+                    if let Some(annotation) = annotation {
+                        self.expect(annotation, actual, span);
+                    }
+                }
+                match return_type {
+                    Some(return_type) => Inferred::new(return_type.into_inner(), true),
+                    None => Inferred::unknown(),
+                }
+            }
+            Expression::Lambda {
+                span,
+                parameters,
+                parameter_types,
+                return_type,
+                body,
+            } => {
+                let span = span.clone();
+                self.check_function(span, parameters, parameter_types, return_type, body);
+                Inferred::new(Type::Function, false)
+            }
+            Expression::Literal(literal) => match literal.deref() {
+                Literal::Nil => Inferred::new(Type::Nil, false),
+                Literal::Boolean(_) => Inferred::new(Type::Boolean, false),
+                Literal::Number(_) => Inferred::new(Type::Number, false),
+                Literal::String(_) => Inferred::new(Type::String, false),
+                Literal::Identifier(identifier) => {
+                    match self.annotation(identifier, &literal.span) {
+                        Some(annotation) => Inferred::new(annotation.into_inner(), true),
+                        None if self.is_function(identifier, &literal.span) => {
+                            Inferred::new(Type::Function, false)
+                        }
+                        None => Inferred::unknown(),
+                    }
+                }
+                Literal::This | Literal::Super => Inferred::unknown(),
+            },
+        }
+    }
+
+    fn check_binary_operator(
+        &mut self,
+        operator: &Spanned<BinaryOperator>,
+        left: Inferred,
+        right: Inferred,
+    ) -> Inferred {
+        // an operation is checked as long as either of its operands is annotated.
+        let annotated = left.annotated || right.annotated;
+        let kind = match operator.deref() {
+            BinaryOperator::Add => match (left.kind, right.kind) {
+                (Type::Number, Type::Number) => Type::Number,
+                (Type::String, Type::String) => Type::String,
+                (Type::Any, Type::Any | Type::Number | Type::String)
+                | (Type::Number | Type::String, Type::Any) => Type::Any,
+                (left, right) => {
+                    if annotated {
+                        let diagnostic = errors::raise("E0009", operator.span.clone())
+                            .with_notes(vec![format!("found {left} and {right}")]);
+                        self.diagnostics.push(diagnostic);
+                    }
+                    Type::Any
+                }
+            },
+            BinaryOperator::Subtract
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo => {
+                self.expect_number(&operator.span, left, annotated);
+                self.expect_number(&operator.span, right, annotated);
+                Type::Number
+            }
+            BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessEqual => {
+                self.expect_number(&operator.span, left, annotated);
+                self.expect_number(&operator.span, right, annotated);
+                Type::Boolean
+            }
+            BinaryOperator::Equal | BinaryOperator::NotEqual => Type::Boolean,
+            // logical operators evaluate to either of the operands.
+            _ => return Self::join(left, right),
+        };
+        Inferred::new(kind, annotated)
+    }
+
+    // Type of an expression evaluating to either of the two.
+    fn join(left: Inferred, right: Inferred) -> Inferred {
+        if left.kind == right.kind {
+            Inferred::new(left.kind, left.annotated || right.annotated)
+        } else {
+            Inferred::unknown()
+        }
+    }
+
+    fn expect(&mut self, annotation: &Spanned<Type>, actual: Inferred, span: Span) {
+        let expected = *annotation.deref();
+        let actual = actual.kind;
+        if expected == Type::Any || actual == Type::Any || expected == actual {
+            return;
+        }
+        self.mismatch(
+            annotation,
+            format!("expected {expected}, found {actual}"),
+            span,
+        );
+    }
+
+    fn mismatch(&mut self, annotation: &Spanned<Type>, note: String, span: Span) {
+        let label = errors::secondary(annotation.span.clone(), "expected due to this annotation");
+        let mut diagnostic = errors::raise("E0028", span).with_notes(vec![note]);
+        diagnostic.labels.push(label);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn expect_number(&mut self, span: &Span, actual: Inferred, annotated: bool) {
+        if annotated && !matches!(actual.kind, Type::Any | Type::Number) {
+            let diagnostic = errors::raise("E0008", span.clone())
+                .with_notes(vec![format!("found {}", actual.kind)]);
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn annotation(&self, identifier: &str, span: &Span) -> Option<Spanned<Type>> {
+        match self.resolutions.get(span)? {
+            Resolution::Local { declaration, .. } | Resolution::Upvalue { declaration, .. } => {
+                self.locals.get(declaration).cloned()
+            }
+            Resolution::Global => self.globals.get(identifier).cloned(),
+        }
+    }
+
+    fn is_function(&self, identifier: &str, span: &Span) -> bool {
+        matches!(self.resolutions.get(span), Some(Resolution::Global))
+            && (self.functions.contains_key(identifier)
+                || NATIVE_FUNCTIONS.iter().any(|(name, _)| *name == identifier))
+    }
+
+    // Signature of the top-level function invoked by name.
+    fn signature(&self, callee: &Expression) -> Option<&Signature> {
+        match callee {
+            Expression::Literal(Spanned {
+                value: Literal::Identifier(identifier),
+                span,
+            }) if self.is_function(identifier, span) => self.functions.get(identifier),
+            _ => None,
+        }
+    }
+}
+
+fn admits_nil(annotation: &Spanned<Type>) -> bool {
+    matches!(annotation.deref(), Type::Any | Type::Nil)
+}
+
+// Whether the statement may complete normally, rather than always returning or throwing.
+// Loops are assumed to complete unless their conditions are literally true.
+fn completes(statement: &Statement) -> bool {
+    let forever = |condition: Option<&Expression>| {
+        matches!(
+            condition,
+            None | Some(Expression::Literal(Spanned {
+                value: Literal::Boolean(true),
+                ..
+            }))
+        )
+    };
+    match statement {
+        Statement::Return { .. } | Statement::Throw { .. } => false,
+        Statement::If {
+            then, otherwise, ..
+        } => completes(then) || otherwise.as_deref().is_none_or(completes),
+        Statement::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            let caught = catch
+                .as_ref()
+                .is_some_and(|(_, handler)| completes(handler));
+            (completes(body) || caught) && finally.as_deref().is_none_or(completes)
+        }
+        Statement::While { condition, .. } => !forever(Some(condition)),
+        Statement::For { condition, .. } => !forever(condition.as_ref()),
+        Statement::Block(declarations) => {
+            declarations.iter().all(|declaration| match declaration {
+                Declaration::Statement(statement) => completes(statement),
+                _ => true,
+            })
+        }
+        Statement::Expression(_) | Statement::Print(_) => true,
+    }
+}
+
+// Check annotated types of a module, along with types of literals and operators, which
+// are inferred. Values of unknown types are never reported.
+pub fn check(program: &[Declaration], resolutions: &Resolutions) -> DiagnosticsResult {
+    let mut checker = Checker::new(resolutions);
+    checker.define_globals(program);
+    for declaration in program {
+        checker.check_declaration(declaration);
+    }
+    if !checker.diagnostics.is_empty() {
+        return Err(checker.diagnostics);
+    }
+    Ok(())
+}
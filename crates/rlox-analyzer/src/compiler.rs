@@ -4,44 +4,111 @@ use std::rc::Rc;
 
 use rlox_intermediate::*;
 
-struct Compiler {
-    offset: usize,
+use crate::{folder, optimizer};
+use crate::resolver::{Resolution, Resolutions};
+
+// Arity of a function known at compile time, with its declaration span if it's not native.
+struct Signature {
+    arity: usize,
+    span: Option<Span>,
+}
+
+// Global slots of a module, allocated to names in order of their first occurrences in
+// the script or any function of it.
+#[derive(Default)]
+struct Globals {
+    slots: HashMap<String, usize>,
+    names: Vec<String>,
+}
+
+impl Globals {
+    // Slot of the global used at `span`, which is allocated at the first time.
+    fn slot(&mut self, name: &str, span: &Span) -> DiagnosableResult<usize> {
+        if let Some(slot) = self.slots.get(name) {
+            return Ok(*slot);
+        }
+        let slot = self.names.len();
+        if slot > LONG_OPERAND {
+            let note = format!("a module has at most {} globals", LONG_OPERAND + 1);
+            raise!("E0029", span.clone(), note);
+        }
+        self.slots.insert(name.to_string(), slot);
+        self.names.push(name.to_string());
+        Ok(slot)
+    }
+}
+
+// A try statement being compiled, whose finally clause is run by returns from it.
+struct Protection<'a> {
+    finally: Option<&'a Statement>,
+    // ranges of finally clauses run by returns, which its handlers never cover.
+    gaps: Vec<(usize, usize)>,
+}
+
+struct Compiler<'a> {
     chunk: ChunkBuilder,
-    locals: Vec<String>,
+    // spans of local declarations, indexed by their stack slots.
+    locals: Vec<Span>,
     blocks: Vec<usize>,
+    // enclosing try statements, from the outermost one.
+    protections: Vec<Protection<'a>>,
+    resolutions: &'a Resolutions,
+    // top-level functions of the module and native functions, invoked by name.
+    signatures: &'a HashMap<String, Signature>,
+    globals: &'a mut Globals,
+    module: usize,
+    // module indices of imported paths.
+    imports: HashMap<String, usize>,
 }
 
-impl Compiler {
-    fn new() -> Self {
+impl<'a> Compiler<'a> {
+    fn new(
+        module: usize,
+        resolutions: &'a Resolutions,
+        signatures: &'a HashMap<String, Signature>,
+        globals: &'a mut Globals,
+    ) -> Self {
         Self {
-            offset: 0,
             chunk: ChunkBuilder::new(),
             locals: Vec::new(),
             blocks: Vec::new(),
+            protections: Vec::new(),
+            resolutions,
+            signatures,
+            globals,
+            module,
+            imports: HashMap::new(),
         }
     }
 
-    fn predefine_parameters(&mut self, parameters: Vec<Spanned<String>>) {
+    fn predefine_parameters(&mut self, parameters: &[Spanned<String>]) -> DiagnosableResult {
         for parameter in parameters {
-            self.locals.push(parameter.into_inner());
+            self.declare(parameter.span.clone())?;
         }
+        Ok(())
     }
 
-    fn compile(mut self, program: Vec<Declaration>) -> DiagnosableResult<Chunk> {
-        while self.offset < program.len() {
-            self.compile_declaration(&program[self.offset])?;
-            self.offset += 1;
+    fn compile(mut self, program: &'a [Declaration]) -> DiagnosableResult<Chunk> {
+        for declaration in program {
+            self.compile_declaration(declaration)?;
         }
+        // script of a module evaluates to the module itself, as the result of importing.
+        self.chunk
+            .write(Instruction::Import(self.module), Default::default());
+        self.chunk.append(Instruction::Return);
         Ok(self.emit())
     }
 
-    fn emit(self) -> Chunk {
+    fn emit(mut self) -> Chunk {
+        optimizer::optimize(&mut self.chunk);
         self.chunk.build()
     }
 
-    fn compile_declaration(&mut self, declaration: &Declaration) -> DiagnosableResult {
+    fn compile_declaration(&mut self, declaration: &'a Declaration) -> DiagnosableResult {
         match declaration {
-            Declaration::Var { name, initializer } => {
+            Declaration::Var {
+                name, initializer, ..
+            } => {
                 // initial value
                 if let Some(initializer) = initializer {
                     self.compile_expression(initializer)?;
@@ -50,24 +117,49 @@ impl Compiler {
                 }
                 // determine whether it is global or local
                 if self.blocks.is_empty() {
-                    // load global variable name (identifier)
-                    let index = self.chunk.define(Constant::String(name.deref().clone()));
+                    self.check_redefinition(name)?;
+                    let slot = self.globals.slot(name, &name.span)?;
                     self.chunk
-                        .write(Instruction::LoadConstant(index), name.span.clone());
-                    self.chunk.append(Instruction::DefineGlobal);
+                        .write(Instruction::DefineGlobal(slot), name.span.clone());
                 } else {
                     // there's no need to generate SetLocal.
                     // local variables are defined once initializer expression calculated.
-                    self.locals.push(name.deref().clone());
+                    self.declare(name.span.clone())?;
                 }
             }
+            Declaration::Import { path, name } => {
+                // imported module is bound to a global variable.
+                let module = self.imports[path.deref()];
+                self.chunk
+                    .write(Instruction::Import(module), path.span.clone());
+                self.check_redefinition(name)?;
+                let slot = self.globals.slot(name, &name.span)?;
+                self.chunk
+                    .write(Instruction::DefineGlobal(slot), name.span.clone());
+            }
+            // nested functions are function values bound to locals, just like lambdas.
+            Declaration::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                self.compile_lambda(name.clone(), parameters, body)?;
+                self.declare(name.span.clone())?;
+            }
+            Declaration::Class { name, .. } => {
+                raise!(
+                    "E0030",
+                    name.span.clone(),
+                    String::from("classes are not supported yet")
+                )
+            }
             Declaration::Statement(statement) => self.compile_statement(statement)?,
-            _ => unimplemented!(),
         }
         Ok(())
     }
 
-    fn compile_statement(&mut self, statement: &Statement) -> DiagnosableResult {
+    fn compile_statement(&mut self, statement: &'a Statement) -> DiagnosableResult {
         match statement {
             Statement::Expression(expression) => {
                 self.compile_expression(expression)?;
@@ -135,11 +227,90 @@ impl Compiler {
                 self.compile_expression(expression)?;
                 self.chunk.append(Instruction::Print);
             }
-            Statement::Return(expression) => {
+            Statement::Return { span, expression } => {
                 if let Some(expression) = expression {
                     self.compile_expression(expression)?;
+                } else {
+                    self.chunk.write(Instruction::Nil, span.clone());
+                }
+                // finally clauses of enclosing try statements are run innermost first, with
+                // the returned value kept as an anonymous local.
+                self.declare(span.clone())?;
+                for index in (0..self.protections.len()).rev() {
+                    let Some(finally) = self.protections[index].finally else {
+                        continue;
+                    };
+                    // returns in the finally clause run only those enclosing it.
+                    let mut inner = self.protections.split_off(index);
+                    let start = self.chunk.instructions.len();
+                    self.compile_statement(finally)?;
+                    let end = self.chunk.instructions.len();
+                    for protection in &mut inner {
+                        protection.gaps.push((start, end));
+                    }
+                    self.protections.append(&mut inner);
+                }
+                self.locals.pop();
+                self.chunk.write(Instruction::Return, span.clone());
+            }
+            Statement::Throw { span, expression } => {
+                self.compile_expression(expression)?;
+                self.chunk.write(Instruction::Throw, span.clone());
+            }
+            Statement::Try {
+                span,
+                body,
+                catch,
+                finally,
+            } => {
+                // locals visible to the try statement survive from unwinding.
+                let depth = self.locals.len();
+                let start = self.chunk.instructions.len();
+                self.protections.push(Protection {
+                    finally: finally.as_deref(),
+                    gaps: Vec::new(),
+                });
+                self.compile_statement(body)?;
+                let mut outer_backpatches = Vec::new();
+                if let Some((name, handler)) = catch {
+                    outer_backpatches.push(
+                        self.chunk
+                            .write_backpatch(Instruction::Jump(0), span.clone()),
+                    );
+                    let gaps = &self.protections.last().unwrap().gaps;
+                    write_handlers(&mut self.chunk, start, depth, gaps);
+                    // the caught exception is pushed by VM, as a local of the catch clause.
+                    self.begin_scope();
+                    self.declare(name.span.clone())?;
+                    self.compile_statement(handler)?;
+                    self.end_scope();
+                }
+                // returns in the finally clause don't run it again.
+                let protection = self.protections.pop().unwrap();
+                if let Some(finally) = finally {
+                    // exceptions thrown in both try and catch clauses are caught, and thrown
+                    // again after finally clause is executed.
+                    outer_backpatches.push(
+                        self.chunk
+                            .write_backpatch(Instruction::Jump(0), span.clone()),
+                    );
+                    write_handlers(&mut self.chunk, start, depth, &protection.gaps);
+                    // an anonymous local is never resolved by identifiers.
+                    self.begin_scope();
+                    self.declare(span.clone())?;
+                    self.compile_statement(finally)?;
+                    self.chunk.write(Instruction::GetLocal(depth), span.clone());
+                    self.chunk.append(Instruction::Throw);
+                    // no need to pop locals since the exception is always thrown.
+                    self.blocks.pop();
+                    self.locals.truncate(depth);
+                }
+                for mut outer_backpatch in outer_backpatches {
+                    outer_backpatch.backpatch();
+                }
+                if let Some(finally) = finally {
+                    self.compile_statement(finally)?;
                 }
-                self.chunk.append(Instruction::Return);
             }
             Statement::While { condition, body } => {
                 let condition_tag = self.chunk.instructions.len();
@@ -165,90 +336,92 @@ impl Compiler {
     }
 
     fn compile_expression(&mut self, expression: &Expression) -> DiagnosableResult {
+        // operations on literals are evaluated at compile time, spanning the whole expression.
+        if let Expression::Binary { .. } | Expression::Unary { .. } = expression {
+            if let Some(literal) = folder::fold(expression) {
+                self.compile_constant(&literal, expression.span())?;
+                return Ok(());
+            }
+        }
         match expression {
             Expression::Assignment { left, span, right } => {
                 // prepare assignment value
                 self.compile_expression(right)?;
-                match left.deref() {
-                    Expression::Binary { .. } => unimplemented!(),
-                    Expression::Literal(literal) => match literal.deref() {
-                        Literal::Identifier(identifier) => {
-                            match self.search_local(identifier) {
-                                None => {
-                                    // load global variable name (identifier)
-                                    let index =
-                                        self.chunk.define(Constant::String(identifier.clone()));
-                                    self.chunk.write(
-                                        Instruction::LoadConstant(index),
-                                        literal.span.clone(),
-                                    );
-                                    self.chunk.append(Instruction::SetGlobal);
-                                }
-                                Some(index) => self.chunk.append(Instruction::SetLocal(index)),
-                            }
-                        }
-                        _ => raise!("E0013", literal.span.clone()),
-                    },
-                    _ => raise!("E0013", span.clone()),
-                }
+                let target = assignment_target(left, span)?;
+                self.set_variable(&target, target.span.clone())?;
             }
-            Expression::Binary {
+            Expression::Ternary {
+                condition,
+                span,
+                then,
+                otherwise,
+            } => {
+                self.compile_expression(condition)?;
+                let mut otherwise_backpatch =
+                    self.chunk.append_backpatch(Instruction::JumpIfFalse(0));
+                self.chunk.append(Instruction::Pop);
+                self.compile_expression(then)?;
+                let mut outer_backpatch = self.chunk.append_backpatch(Instruction::Jump(0));
+                otherwise_backpatch.backpatch();
+                self.chunk.append(Instruction::Pop);
+                self.compile_expression(otherwise)?;
+                outer_backpatch.backpatch();
+            }
+            Expression::CompoundAssignment {
                 left,
                 operator,
                 right,
             } => {
-                let span = operator.span.clone();
-                match operator.deref() {
-                    BinaryOperator::And => {
-                        self.compile_expression(left)?;
-                        let mut outer_backpatch =
-                            self.chunk.append_backpatch(Instruction::JumpIfFalse(0));
-                        self.chunk.append(Instruction::Pop);
-                        self.compile_expression(right)?;
-                        outer_backpatch.backpatch();
-                    }
-                    BinaryOperator::Or => {
-                        self.compile_expression(left)?;
-                        let mut right_backpatch =
-                            self.chunk.append_backpatch(Instruction::JumpIfFalse(0));
-                        let mut outer_backpatch = self.chunk.append_backpatch(Instruction::Jump(0));
-                        right_backpatch.backpatch();
-                        self.chunk.append(Instruction::Pop);
-                        self.compile_expression(right)?;
-                        outer_backpatch.backpatch();
-                    }
-                    _ => {
-                        self.compile_expression(left)?;
-                        self.compile_expression(right)?;
-                        match operator.deref() {
-                            BinaryOperator::Add => self.chunk.write(Instruction::Add, span),
-                            BinaryOperator::Subtract => {
-                                self.chunk.write(Instruction::Subtract, span)
-                            }
-                            BinaryOperator::Multiply => {
-                                self.chunk.write(Instruction::Multiply, span)
-                            }
-                            BinaryOperator::Divide => self.chunk.write(Instruction::Divide, span),
-                            BinaryOperator::Equal => self.chunk.write(Instruction::Equal, span),
-                            BinaryOperator::Greater => self.chunk.write(Instruction::Greater, span),
-                            BinaryOperator::Less => self.chunk.write(Instruction::Less, span),
-                            BinaryOperator::NotEqual => {
-                                self.chunk.write(Instruction::Equal, span);
-                                self.chunk.append(Instruction::Not);
-                            }
-                            BinaryOperator::GreaterEqual => {
-                                self.chunk.write(Instruction::Less, span);
-                                self.chunk.append(Instruction::Not);
-                            }
-                            BinaryOperator::LessEqual => {
-                                self.chunk.write(Instruction::Greater, span);
-                                self.chunk.append(Instruction::Not);
-                            }
-                            _ => unimplemented!(),
-                        }
-                    }
-                }
+                // the target is loaded once, combined with the value and stored back.
+                let target = assignment_target(left, &operator.span)?;
+                self.get_variable(&target, target.span.clone())?;
+                self.compile_expression(right)?;
+                self.compile_binary_operator(operator);
+                self.set_variable(&target, target.span.clone())?;
             }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => match operator.deref() {
+                BinaryOperator::And => {
+                    self.compile_expression(left)?;
+                    let mut outer_backpatch =
+                        self.chunk.append_backpatch(Instruction::JumpIfFalse(0));
+                    self.chunk.append(Instruction::Pop);
+                    self.compile_expression(right)?;
+                    outer_backpatch.backpatch();
+                }
+                BinaryOperator::PropertyAccess => {
+                    self.compile_expression(left)?;
+                    let property = match right.deref() {
+                        Expression::Literal(Spanned {
+                            value: Literal::Identifier(identifier),
+                            ..
+                        }) => identifier,
+                        Expression::Literal(literal) => raise!("E0010", literal.span.clone()),
+                        _ => raise!("E0010", operator.span.clone()),
+                    };
+                    let index = self.define(Constant::String(property.clone()), &operator.span)?;
+                    self.chunk
+                        .write(Instruction::GetProperty(index), operator.span.clone());
+                }
+                BinaryOperator::Or => {
+                    self.compile_expression(left)?;
+                    let mut right_backpatch =
+                        self.chunk.append_backpatch(Instruction::JumpIfFalse(0));
+                    let mut outer_backpatch = self.chunk.append_backpatch(Instruction::Jump(0));
+                    right_backpatch.backpatch();
+                    self.chunk.append(Instruction::Pop);
+                    self.compile_expression(right)?;
+                    outer_backpatch.backpatch();
+                }
+                _ => {
+                    self.compile_expression(left)?;
+                    self.compile_expression(right)?;
+                    self.compile_binary_operator(operator);
+                }
+            },
             Expression::Unary {
                 operator,
                 expression,
@@ -260,6 +433,30 @@ impl Compiler {
                     UnaryOperator::Negate => self.chunk.write(Instruction::Negate, span),
                 }
             }
+            Expression::Update {
+                operator,
+                prefix,
+                expression,
+            } => {
+                let target = assignment_target(expression, &operator.span)?;
+                let span = operator.span.clone();
+                self.get_variable(&target, target.span.clone())?;
+                // postfix version evaluates to the old value, so keep a copy of it.
+                if !prefix {
+                    self.chunk.write(Instruction::Duplicate, span.clone());
+                }
+                let index = self.define(Constant::Number(1.0), &span)?;
+                self.chunk
+                    .write(Instruction::LoadConstant(index), span.clone());
+                match operator.deref() {
+                    UpdateOperator::Increment => self.chunk.write(Instruction::Add, span),
+                    UpdateOperator::Decrement => self.chunk.write(Instruction::Subtract, span),
+                }
+                self.set_variable(&target, target.span.clone())?;
+                if !prefix {
+                    self.chunk.append(Instruction::Pop);
+                }
+            }
             Expression::Invocation {
                 expression,
                 arguments,
@@ -271,66 +468,225 @@ impl Compiler {
                 }
                 match expression.deref() {
                     Expression::Literal(literal) => match literal.deref() {
-                        Literal::Identifier(identifier) => {
-                            let index = self.chunk.define(Constant::String(identifier.clone()));
-                            self.chunk
-                                .write(Instruction::LoadConstant(index), literal.span.clone());
-                        }
+                        // locals may hold function values, or the function is invoked by its slot.
+                        Literal::Identifier(identifier) => match self.resolve(&literal.span)? {
+                            Some(slot) => {
+                                self.chunk
+                                    .write(Instruction::GetLocal(slot), literal.span.clone());
+                                self.chunk.append(Instruction::Invoke);
+                            }
+                            None => {
+                                self.check_arity(identifier, &literal.span, arguments.len())?;
+                                // native functions take precedence over globals of their names.
+                                let native = NATIVE_FUNCTIONS
+                                    .iter()
+                                    .position(|(name, _)| name == identifier);
+                                let instruction = match native {
+                                    Some(index) => Instruction::InvokeNative(index),
+                                    None => {
+                                        let slot = self.globals.slot(identifier, &literal.span)?;
+                                        Instruction::InvokeGlobal(slot)
+                                    }
+                                };
+                                self.chunk.write(instruction, literal.span.clone());
+                            }
+                        },
                         _ => raise!("E0014", literal.span.clone()),
                     },
-                    _ => unimplemented!(),
+                    // any other expression evaluates to the invoked function value.
+                    _ => {
+                        self.compile_expression(expression)?;
+                        self.chunk.append(Instruction::Invoke);
+                    }
                 }
-                self.chunk.append(Instruction::Invoke);
+            }
+            Expression::Lambda {
+                span,
+                parameters,
+                body,
+                ..
+            } => {
+                let name = Spanned::new(String::from("<lambda>"), span.clone());
+                self.compile_lambda(name, parameters, body)?;
             }
             Expression::Literal(literal) => match literal.deref() {
-                Literal::Nil => self.chunk.write(Instruction::Nil, literal.span.clone()),
-                Literal::Boolean(boolean) => {
-                    if *boolean {
-                        self.chunk.write(Instruction::True, literal.span.clone());
-                    } else {
-                        self.chunk.write(Instruction::False, literal.span.clone());
-                    }
-                }
-                Literal::Number(number) => {
-                    let index = self.chunk.define(Constant::Number(*number));
-                    self.chunk
-                        .write(Instruction::LoadConstant(index), literal.span.clone());
-                }
-                Literal::String(string) => {
-                    let index = self.chunk.define(Constant::String(string.clone()));
-                    self.chunk
-                        .write(Instruction::LoadConstant(index), literal.span.clone());
-                }
                 Literal::Identifier(identifier) => {
-                    // determine whether it is global or local
-                    match self.search_local(identifier) {
-                        None => {
-                            // load global variable name (identifier)
-                            let index = self.chunk.define(Constant::String(identifier.clone()));
-                            self.chunk
-                                .write(Instruction::LoadConstant(index), literal.span.clone());
-                            self.chunk.append(Instruction::GetGlobal);
-                        }
-                        Some(index) => self
-                            .chunk
-                            .write(Instruction::GetLocal(index), literal.span.clone()),
-                    }
+                    self.get_variable(identifier, literal.span.clone())?
+                }
+                Literal::This => raise!(
+                    "E0030",
+                    literal.span.clone(),
+                    String::from("`this` is not supported yet")
+                ),
+                Literal::Super => {
+                    raise!(
+                        "E0030",
+                        literal.span.clone(),
+                        String::from("`super` is not supported yet")
+                    )
                 }
-                _ => unimplemented!(),
+                literal_value => self.compile_constant(literal_value, literal.span.clone())?,
             },
         }
         Ok(())
     }
 
-    fn search_local(&self, identifier: &String) -> Option<usize> {
-        let mut local_index = None;
-        for (index, name) in self.locals.iter().rev().enumerate() {
-            if name == identifier {
-                local_index = Some(self.locals.len() - index - 1);
-                break;
+    // Load the function value, which is compiled into a constant.
+    fn compile_lambda(
+        &mut self,
+        name: Spanned<String>,
+        parameters: &[Spanned<String>],
+        body: &Statement,
+    ) -> DiagnosableResult {
+        let span = name.span.clone();
+        let function = compile_function(
+            name,
+            parameters,
+            body,
+            self.module,
+            self.resolutions,
+            self.signatures,
+            self.globals,
+        )?;
+        let index = self.define(Constant::Function(Rc::new(function)), &span)?;
+        self.chunk.write(Instruction::LoadConstant(index), span);
+        Ok(())
+    }
+
+    // nil, booleans, numbers and strings only, other literals are not constants.
+    fn compile_constant(&mut self, literal: &Literal, span: Span) -> DiagnosableResult {
+        match literal {
+            Literal::Nil => self.chunk.write(Instruction::Nil, span),
+            Literal::Boolean(true) => self.chunk.write(Instruction::True, span),
+            Literal::Boolean(false) => self.chunk.write(Instruction::False, span),
+            Literal::Number(number) => {
+                let index = self.define(Constant::Number(*number), &span)?;
+                self.chunk.write(Instruction::LoadConstant(index), span);
             }
+            Literal::String(string) => {
+                let index = self.define(Constant::String(string.clone()), &span)?;
+                self.chunk.write(Instruction::LoadConstant(index), span);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Index of the constant used at `span` in the pool.
+    fn define(&mut self, constant: Constant, span: &Span) -> DiagnosableResult<usize> {
+        let index = self.chunk.define(constant);
+        if index > LONG_OPERAND {
+            let note = format!("a chunk has at most {} constants", LONG_OPERAND + 1);
+            raise!("E0029", span.clone(), note);
         }
-        local_index
+        Ok(index)
+    }
+
+    // Declare a local at the next stack slot, where `declaration` is where it's resolved to.
+    fn declare(&mut self, declaration: Span) -> DiagnosableResult {
+        if self.locals.len() > LONG_OPERAND {
+            let note = format!("a chunk has at most {} locals in scope", LONG_OPERAND + 1);
+            raise!("E0029", declaration, note);
+        }
+        self.locals.push(declaration);
+        Ok(())
+    }
+
+    // arithmetic, relational and equality operators only, logical operators need jumps.
+    fn compile_binary_operator(&mut self, operator: &Spanned<BinaryOperator>) {
+        let span = operator.span.clone();
+        match operator.deref() {
+            BinaryOperator::Add => self.chunk.write(Instruction::Add, span),
+            BinaryOperator::Subtract => self.chunk.write(Instruction::Subtract, span),
+            BinaryOperator::Multiply => self.chunk.write(Instruction::Multiply, span),
+            BinaryOperator::Divide => self.chunk.write(Instruction::Divide, span),
+            BinaryOperator::Modulo => self.chunk.write(Instruction::Modulo, span),
+            BinaryOperator::Equal => self.chunk.write(Instruction::Equal, span),
+            BinaryOperator::Greater => self.chunk.write(Instruction::Greater, span),
+            BinaryOperator::Less => self.chunk.write(Instruction::Less, span),
+            BinaryOperator::NotEqual => self.chunk.write(Instruction::NotEqual, span),
+            BinaryOperator::GreaterEqual => self.chunk.write(Instruction::GreaterEqual, span),
+            BinaryOperator::LessEqual => self.chunk.write(Instruction::LessEqual, span),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn get_variable(&mut self, identifier: &str, span: Span) -> DiagnosableResult {
+        // determine whether it is global or local
+        match self.resolve(&span)? {
+            None => {
+                let slot = self.globals.slot(identifier, &span)?;
+                self.chunk.write(Instruction::GetGlobal(slot), span);
+            }
+            Some(slot) => self.chunk.write(Instruction::GetLocal(slot), span),
+        }
+        Ok(())
+    }
+
+    // assigned value is expected to be on the stack top, and is left there.
+    fn set_variable(&mut self, identifier: &str, span: Span) -> DiagnosableResult {
+        match self.resolve(&span)? {
+            None => {
+                let slot = self.globals.slot(identifier, &span)?;
+                self.chunk.write(Instruction::SetGlobal(slot), span);
+            }
+            Some(slot) => self.chunk.write(Instruction::SetLocal(slot), span),
+        }
+        Ok(())
+    }
+
+    // Stack slot of the local which the identifier at `span` resolves to, or `None` if it
+    // resolves to a global.
+    fn resolve(&self, span: &Span) -> DiagnosableResult<Option<usize>> {
+        match &self.resolutions[span] {
+            Resolution::Local { declaration, .. } => {
+                let slot = self.locals.iter().rposition(|local| local == declaration);
+                Ok(Some(slot.expect("resolved local is not in scope")))
+            }
+            // closures are not supported yet.
+            Resolution::Upvalue { .. } => raise!("E0017", span.clone()),
+            Resolution::Global => Ok(None),
+        }
+    }
+
+    // Top-level functions are defined in their global slots before the script runs, which
+    // are never defined again.
+    fn check_redefinition(&self, name: &Spanned<String>) -> DiagnosableResult {
+        let Some(Signature {
+            span: Some(function),
+            ..
+        }) = self.signatures.get(name.deref())
+        else {
+            return Ok(());
+        };
+        let label = errors::secondary(function.clone(), "function defined here");
+        Err(Box::new(
+            errors::raise("E0011", name.span.clone()).with_labels(vec![label]),
+        ))
+    }
+
+    // Functions invoked by name are checked statically, while function values are still
+    // checked by the VM.
+    fn check_arity(&self, name: &str, span: &Span, argument_count: usize) -> DiagnosableResult {
+        let Some(signature) = self.signatures.get(name) else {
+            return Ok(());
+        };
+        if signature.arity == argument_count {
+            return Ok(());
+        }
+        let mut diagnostic = errors::raise("E0016", span.clone()).with_notes(vec![format!(
+            "expected {} arguments, found {}",
+            signature.arity, argument_count
+        )]);
+        match &signature.span {
+            Some(span) => diagnostic
+                .labels
+                .push(errors::secondary(span.clone(), "function defined here")),
+            None => diagnostic
+                .notes
+                .push(format!("`{name}` is a native function")),
+        }
+        Err(Box::new(diagnostic))
     }
 
     fn begin_scope(&mut self) {
@@ -346,7 +702,104 @@ impl Compiler {
     }
 }
 
-pub fn compile(program: Vec<Declaration>) -> DiagnosableResult<Bytecode> {
+// Extract the assignable variable from the target of an (compound) assignment or
+// increment/decrement, with `span` pointing to the operator in case it's not assignable.
+fn assignment_target<'a>(
+    target: &'a Expression,
+    span: &Span,
+) -> DiagnosableResult<Spanned<&'a String>> {
+    match target {
+        Expression::Literal(literal) => match literal.deref() {
+            Literal::Identifier(identifier) => Ok(Spanned::new(identifier, literal.span.clone())),
+            _ => raise!("E0013", literal.span.clone()),
+        },
+        _ => raise!("E0013", span.clone()),
+    }
+}
+
+// Write handlers from `start` to the next instruction, which they jump to, apart from gaps.
+fn write_handlers(chunk: &mut ChunkBuilder, start: usize, depth: usize, gaps: &[(usize, usize)]) {
+    let target = chunk.instructions.len();
+    let mut start = start;
+    for &(end, next) in gaps.iter().chain([&(target, target)]) {
+        if start < end {
+            chunk.handlers.push(Handler {
+                start,
+                end,
+                target,
+                depth,
+            });
+        }
+        start = next;
+    }
+}
+
+fn compile_function(
+    name: Spanned<String>,
+    parameters: &[Spanned<String>],
+    body: &Statement,
+    module: usize,
+    resolutions: &Resolutions,
+    signatures: &HashMap<String, Signature>,
+    globals: &mut Globals,
+) -> DiagnosableResult<Function> {
+    let mut compiler = Compiler::new(module, resolutions, signatures, globals);
+    compiler.begin_scope(); // everything in a function is local
+    compiler.predefine_parameters(parameters)?; // and parameters are actually local variables
+    compiler.compile_statement(body)?;
+    Ok(Function {
+        name: name.value,
+        span: name.span,
+        chunk: Rc::new(compiler.emit()),
+        arity: parameters.len(),
+        module,
+    })
+}
+
+// Compile declarations of a source file into the module at index `module`, with its
+// imported paths already resolved to module indices.
+pub fn compile(
+    program: Vec<Declaration>,
+    name: String,
+    file: FileId,
+    module: usize,
+    imports: HashMap<String, usize>,
+    resolutions: &Resolutions,
+) -> DiagnosableResult<Module> {
+    // signatures are collected first, since functions may invoke those declared later.
+    // Functions are globals as well, whose slots come first.
+    let mut signatures = HashMap::new();
+    let mut globals = Globals::default();
+    for declaration in &program {
+        if let Declaration::Function {
+            name, parameters, ..
+        } = declaration
+        {
+            let signature = Signature {
+                arity: parameters.len(),
+                span: Some(name.span.clone()),
+            };
+            if let Some(Signature {
+                span: Some(previous),
+                ..
+            }) = signatures.insert(name.deref().clone(), signature)
+            {
+                let label = errors::secondary(previous, "function defined here");
+                let diagnostic = errors::raise("E0011", name.span.clone()).with_labels(vec![label]);
+                return Err(Box::new(diagnostic));
+            }
+            globals.slot(name, &name.span)?;
+        }
+    }
+    // native functions take precedence over functions of the same names in the VM.
+    for (name, arity) in NATIVE_FUNCTIONS {
+        let signature = Signature {
+            arity: *arity,
+            span: None,
+        };
+        signatures.insert(name.to_string(), signature);
+    }
+
     let mut script = Vec::new();
     let mut functions = HashMap::new();
     for declaration in program {
@@ -355,56 +808,30 @@ pub fn compile(program: Vec<Declaration>) -> DiagnosableResult<Bytecode> {
                 name,
                 parameters,
                 body,
+                ..
             } => {
-                let mut compiler = Compiler::new();
-                let arity = parameters.len();
-                compiler.begin_scope(); // everything in a function is local
-                compiler.predefine_parameters(parameters); // and parameters are actually local variables
-                compiler.compile_statement(&body)?;
-                let function = Function {
-                    chunk: Rc::new(compiler.emit()),
-                    arity,
-                };
-                functions.insert(name.into_inner(), function);
+                let function = compile_function(
+                    name.clone(),
+                    &parameters,
+                    &body,
+                    module,
+                    resolutions,
+                    &signatures,
+                    &mut globals,
+                )?;
+                functions.insert(name.into_inner(), Rc::new(function));
             }
             _ => script.push(declaration),
         }
     }
-    let script = Rc::new(Compiler::new().compile(script)?);
-    let bytecode = Bytecode {
+    let mut compiler = Compiler::new(module, resolutions, &signatures, &mut globals);
+    compiler.imports = imports;
+    let script = Rc::new(compiler.compile(&script)?);
+    Ok(Module {
+        name,
+        file,
         functions,
-        script: Rc::clone(&script),
-    };
-    #[cfg(feature = "bytecode-preview")]
-    {
-        println!("━━━━━━━━━━ Bytecode Preview Start ━━━━━━━━━━");
-        for (name, function) in &bytecode.functions {
-            println!("function \"{name}\", arity = {}", function.arity);
-            preview_chunk(&function.chunk);
-            println!();
-        }
-        if !script.is_empty() {
-            println!("<script>");
-            preview_chunk(&bytecode.script);
-        }
-    }
-    Ok(bytecode)
-}
-
-#[cfg(feature = "bytecode-preview")]
-fn preview_chunk(chunk: &Chunk) {
-    if chunk.is_empty() {
-        return;
-    }
-
-    println!("INSTRUCTIONS ({}):", chunk.len());
-    for (index, instruction) in chunk.iter().enumerate() {
-        println!("    {index:04} {instruction:?}");
-    }
-    if !chunk.constants().is_empty() {
-        println!("CONSTANTS ({}):", chunk.constants().len());
-        for (index, constant) in chunk.constants().iter().enumerate() {
-            println!("    {index:03} {constant:?}");
-        }
-    }
+        globals: globals.names,
+        script,
+    })
 }
@@ -0,0 +1,82 @@
+use std::ops::Deref;
+
+use rlox_intermediate::*;
+
+// Evaluate an expression of literals at compile time, as the VM would do at runtime.
+//
+// Operations that would raise (e.g. `"a" - 1`) are never folded, so that the runtime
+// diagnostic still fires with its own span.
+pub fn fold(expression: &Expression) -> Option<Literal> {
+    match expression {
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = fold(left)?;
+            let right = fold(right)?;
+            fold_binary(operator, left, right)
+        }
+        Expression::Unary {
+            operator,
+            expression,
+        } => {
+            let value = fold(expression)?;
+            match (operator.deref(), value) {
+                (UnaryOperator::Not, value) => Some(Literal::Boolean(!truthy(&value))),
+                (UnaryOperator::Negate, Literal::Number(number)) => Some(Literal::Number(-number)),
+                _ => None,
+            }
+        }
+        Expression::Literal(literal) => match literal.deref() {
+            Literal::Nil => Some(Literal::Nil),
+            Literal::Boolean(boolean) => Some(Literal::Boolean(*boolean)),
+            Literal::Number(number) => Some(Literal::Number(*number)),
+            Literal::String(string) => Some(Literal::String(string.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn fold_binary(operator: &BinaryOperator, left: Literal, right: Literal) -> Option<Literal> {
+    use Literal::{Boolean, Number};
+
+    let literal = match (operator, left, right) {
+        (BinaryOperator::Add, Number(left), Number(right)) => Number(left + right),
+        (BinaryOperator::Add, Literal::String(left), Literal::String(right)) => {
+            Literal::String(left + &right)
+        }
+        (BinaryOperator::Subtract, Number(left), Number(right)) => Number(left - right),
+        (BinaryOperator::Multiply, Number(left), Number(right)) => Number(left * right),
+        (BinaryOperator::Divide, Number(left), Number(right)) => Number(left / right),
+        (BinaryOperator::Modulo, Number(left), Number(right)) => Number(left % right),
+        (BinaryOperator::Greater, Number(left), Number(right)) => Boolean(left > right),
+        (BinaryOperator::GreaterEqual, Number(left), Number(right)) => Boolean(left >= right),
+        (BinaryOperator::Less, Number(left), Number(right)) => Boolean(left < right),
+        (BinaryOperator::LessEqual, Number(left), Number(right)) => Boolean(left <= right),
+        (BinaryOperator::Equal, left, right) => Boolean(equal(&left, &right)),
+        (BinaryOperator::NotEqual, left, right) => Boolean(!equal(&left, &right)),
+        // logical operators evaluate to either of the operands.
+        (BinaryOperator::And, left, right) if truthy(&left) => right,
+        (BinaryOperator::And, left, _) => left,
+        (BinaryOperator::Or, left, _) if truthy(&left) => left,
+        (BinaryOperator::Or, _, right) => right,
+        _ => return None,
+    };
+    Some(literal)
+}
+
+fn truthy(literal: &Literal) -> bool {
+    !matches!(literal, Literal::Nil | Literal::Boolean(false))
+}
+
+fn equal(left: &Literal, right: &Literal) -> bool {
+    match (left, right) {
+        (Literal::Nil, Literal::Nil) => true,
+        (Literal::Boolean(this), Literal::Boolean(that)) => this == that,
+        (Literal::Number(this), Literal::Number(that)) => this == that,
+        (Literal::String(this), Literal::String(that)) => this == that,
+        _ => false,
+    }
+}
@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+pub mod checker;
 pub mod compiler;
+pub mod folder;
+pub mod linter;
+pub mod loader;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
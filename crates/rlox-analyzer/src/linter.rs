@@ -0,0 +1,377 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use rlox_intermediate::*;
+
+use crate::folder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// Levels of warnings, which are all `Level::Warn` unless specified.
+#[derive(Debug, Clone)]
+pub struct Lints {
+    default: Level,
+    levels: HashMap<String, Level>,
+}
+
+impl Lints {
+    pub fn new() -> Self {
+        Self {
+            default: Level::Warn,
+            levels: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, code: impl Into<String>, level: Level) {
+        self.levels.insert(code.into(), level);
+    }
+
+    // Levels specified previously are overridden, so that the last one always wins.
+    pub fn set_all(&mut self, level: Level) {
+        self.default = level;
+        self.levels.clear();
+    }
+
+    pub fn level(&self, code: &str) -> Level {
+        *self.levels.get(code).unwrap_or(&self.default)
+    }
+}
+
+impl Default for Lints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum LocalKind {
+    Variable,
+    Parameter,
+    // bindings of catch clauses are never reported as unused.
+    Binding,
+}
+
+struct Local {
+    name: Spanned<String>,
+    kind: LocalKind,
+    used: bool,
+}
+
+struct Linter<'a> {
+    lints: &'a Lints,
+    globals: HashSet<String>,
+    scopes: Vec<Vec<Local>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(lints: &'a Lints) -> Self {
+        Self {
+            lints,
+            globals: HashSet::new(),
+            scopes: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, mut diagnostic: Diagnostic) {
+        let code = diagnostic.code.as_deref().unwrap_or_default();
+        match self.lints.level(code) {
+            Level::Allow => return,
+            Level::Warn => {}
+            Level::Deny => {
+                diagnostic.severity = Severity::Error;
+                diagnostic.notes.push(format!("warning {code} is denied"));
+            }
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    // Globals may be assigned before they are defined (e.g. in a function), so all of the
+    // top level names are collected first.
+    fn define_globals(&mut self, program: &[Declaration]) {
+        for declaration in program {
+            match declaration {
+                Declaration::Class { name, .. }
+                | Declaration::Function { name, .. }
+                | Declaration::Var { name, .. }
+                | Declaration::Import { name, .. } => {
+                    self.globals.insert(name.deref().clone());
+                }
+                Declaration::Statement(_) => {}
+            }
+        }
+    }
+
+    fn lint_declarations(&mut self, declarations: &[Declaration]) {
+        let mut reported = false;
+        for (index, declaration) in declarations.iter().enumerate() {
+            self.lint_declaration(declaration);
+            if let Declaration::Statement(
+                Statement::Return { span, .. } | Statement::Throw { span, .. },
+            ) = declaration
+            {
+                if !reported && index + 1 < declarations.len() {
+                    self.report(errors::warn("W0003", span.clone()));
+                    reported = true;
+                }
+            }
+        }
+    }
+
+    fn lint_declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Class { functions, .. } => {
+                for function in functions {
+                    self.lint_declaration(function);
+                }
+            }
+            Declaration::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                // nested functions are locals, which are declared before their bodies.
+                self.declare(name, LocalKind::Variable);
+                self.lint_function(parameters, body);
+            }
+            Declaration::Var {
+                name, initializer, ..
+            } => {
+                if let Some(initializer) = initializer {
+                    self.lint_expression(initializer);
+                }
+                self.declare(name, LocalKind::Variable);
+            }
+            Declaration::Import { .. } => {}
+            Declaration::Statement(statement) => self.lint_statement(statement),
+        }
+    }
+
+    fn lint_function(&mut self, parameters: &[Spanned<String>], body: &Statement) {
+        self.begin_scope();
+        for parameter in parameters {
+            self.declare(parameter, LocalKind::Parameter);
+        }
+        self.lint_statement(body);
+        self.end_scope();
+    }
+
+    fn lint_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) | Statement::Print(expression) => {
+                self.lint_expression(expression)
+            }
+            Statement::For {
+                initializer,
+                condition,
+                incrementer,
+                body,
+            } => {
+                self.begin_scope();
+                match initializer {
+                    Some(ForLoopInitializer::VarDeclaration(declaration)) => {
+                        self.lint_declaration(declaration)
+                    }
+                    Some(ForLoopInitializer::VarInitialization(expression)) => {
+                        self.lint_expression(expression)
+                    }
+                    None => {}
+                }
+                for expression in [condition, incrementer].into_iter().flatten() {
+                    self.lint_expression(expression);
+                }
+                self.lint_statement(body);
+                self.end_scope();
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // conditions folded at compile time never change.
+                if folder::fold(condition).is_some() {
+                    self.report(errors::warn("W0005", condition.span()));
+                }
+                self.lint_expression(condition);
+                self.lint_statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.lint_statement(otherwise);
+                }
+            }
+            Statement::Return { expression, .. } => {
+                if let Some(expression) = expression {
+                    self.lint_expression(expression);
+                }
+            }
+            Statement::Throw { expression, .. } => self.lint_expression(expression),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.lint_statement(body);
+                if let Some((name, body)) = catch {
+                    self.begin_scope();
+                    self.declare(name, LocalKind::Binding);
+                    self.lint_statement(body);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.lint_statement(finally);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.lint_expression(condition);
+                self.lint_statement(body);
+            }
+            Statement::Block(declarations) => {
+                self.begin_scope();
+                self.lint_declarations(declarations);
+                self.end_scope();
+            }
+        }
+    }
+
+    fn lint_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Assignment { left, right, .. } => {
+                if !self.lint_undefined(left) {
+                    self.lint_expression(left);
+                }
+                self.lint_expression(right);
+            }
+            Expression::Ternary {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.lint_expression(condition);
+                self.lint_expression(then);
+                self.lint_expression(otherwise);
+            }
+            // compound assignments and updates read their targets as well.
+            Expression::CompoundAssignment { left, right, .. } => {
+                self.lint_undefined(left);
+                self.lint_expression(left);
+                self.lint_expression(right);
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.lint_expression(left);
+                // the right operand of property access is a name, rather than a variable.
+                if !matches!(operator.deref(), BinaryOperator::PropertyAccess) {
+                    self.lint_expression(right);
+                }
+            }
+            Expression::Unary { expression, .. } => self.lint_expression(expression),
+            Expression::Update { expression, .. } => {
+                self.lint_undefined(expression);
+                self.lint_expression(expression);
+            }
+            Expression::Invocation {
+                expression,
+                arguments,
+            } => {
+                self.lint_expression(expression);
+                for argument in arguments {
+                    self.lint_expression(argument);
+                }
+            }
+            Expression::Lambda {
+                parameters, body, ..
+            } => self.lint_function(parameters, body),
+            Expression::Literal(literal) => {
+                if let Literal::Identifier(identifier) = literal.deref() {
+                    if let Some(local) = self.search_local(identifier) {
+                        local.used = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Report the target of an assignment if it's a global never defined, and tell whether
+    // it's a variable at all.
+    fn lint_undefined(&mut self, target: &Expression) -> bool {
+        let Expression::Literal(Spanned {
+            value: Literal::Identifier(identifier),
+            span,
+        }) = target
+        else {
+            return false;
+        };
+        if self.search_local(identifier).is_none() && !self.globals.contains(identifier) {
+            self.report(errors::warn("W0006", span.clone()));
+        }
+        true
+    }
+
+    // Variables declared at top level are globals, which are never linted.
+    fn declare(&mut self, name: &Spanned<String>, kind: LocalKind) {
+        let Some((innermost, enclosing)) = self.scopes.split_last() else {
+            return;
+        };
+        let shadowed = enclosing
+            .iter()
+            .flatten()
+            .rfind(|local| local.name.deref() == name.deref());
+        if let Some(shadowed) = shadowed {
+            let label = errors::secondary(shadowed.name.span.clone(), "shadowed one is here");
+            let mut diagnostic = errors::warn("W0004", name.span.clone());
+            diagnostic.labels.push(label);
+            self.report(diagnostic);
+        }
+        let local = Local {
+            name: name.clone(),
+            kind,
+            used: false,
+        };
+        self.scopes.last_mut().unwrap().push(local);
+    }
+
+    fn search_local(&mut self, identifier: &String) -> Option<&mut Local> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name.deref() == identifier)
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        for local in self.scopes.pop().unwrap() {
+            let code = match (local.used, local.kind) {
+                (false, LocalKind::Variable) => "W0001",
+                (false, LocalKind::Parameter) => "W0002",
+                _ => continue,
+            };
+            self.report(errors::warn(code, local.name.span));
+        }
+    }
+}
+
+// Check a module for suspicious code, which is valid but probably not intended.
+//
+// Warnings of `Level::Allow` are dropped, and those of `Level::Deny` are promoted to errors.
+pub fn lint(program: &[Declaration], lints: &Lints) -> Vec<Diagnostic> {
+    let mut linter = Linter::new(lints);
+    linter.define_globals(program);
+    linter.lint_declarations(program);
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.labels.first().map(|label| label.range.start));
+    diagnostics
+}
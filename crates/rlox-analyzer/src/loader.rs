@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use rlox_intermediate::*;
+
+use crate::linter::Lints;
+use crate::{checker, compiler, linter, parser, resolver, scanner};
+
+struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    lints: &'a Lints,
+    warnings: &'a mut Vec<Diagnostic>,
+    // modules are reserved before compiled, so that imported ones get larger indices.
+    modules: Vec<Option<Module>>,
+    cache: HashMap<PathBuf, usize>,
+    // paths of modules being loaded, from the main script to the innermost import, along
+    // with the span of the import declaration which started loading each of them.
+    loading: Vec<(PathBuf, Option<Span>)>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(
+        sources: &'a mut SourceMap,
+        lints: &'a Lints,
+        warnings: &'a mut Vec<Diagnostic>,
+    ) -> Self {
+        Self {
+            sources,
+            lints,
+            warnings,
+            modules: Vec::new(),
+            cache: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    fn load_module(
+        &mut self,
+        name: String,
+        file: FileId,
+        directory: &Path,
+    ) -> DiagnosticsResult<usize> {
+        let module = self.modules.len();
+        self.modules.push(None);
+
+        let (tokens, diagnostics) = scanner::scan(self.sources.source(file), file);
+        let program = parser::parse(tokens, diagnostics)?;
+        // denied warnings stop the module from being compiled, just like errors.
+        let (denied, warnings): (Vec<_>, Vec<_>) = linter::lint(&program, self.lints)
+            .into_iter()
+            .partition(|diagnostic| diagnostic.severity == Severity::Error);
+        self.warnings.extend(warnings);
+        if !denied.is_empty() {
+            return Err(denied);
+        }
+        let resolutions = resolver::resolve(&program)?;
+        checker::check(&program, &resolutions)?;
+        // imported modules are loaded (and compiled) before the importer.
+        let mut imports = HashMap::new();
+        for declaration in &program {
+            if let Declaration::Import { path, .. } = declaration {
+                let imported = self.import(path, directory)?;
+                imports.insert(path.deref().clone(), imported);
+            }
+        }
+        let compiled = compiler::compile(program, name, file, module, imports, &resolutions);
+        self.modules[module] = Some(compiled.map_err(|d| vec![*d])?);
+        Ok(module)
+    }
+
+    // Resolve the path relative to the importing file, and load the module if it's never
+    // loaded before.
+    fn import(&mut self, path: &Spanned<String>, directory: &Path) -> DiagnosticsResult<usize> {
+        let resolved = directory.join(path.deref());
+        let canonical = match resolved.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(error) => {
+                let diagnostic = errors::raise("E0019", path.span.clone())
+                    .with_notes(vec![format!("{}: {error}", resolved.display())]);
+                return Err(vec![diagnostic]);
+            }
+        };
+        if let Some(position) = self
+            .loading
+            .iter()
+            .position(|(loading, _)| loading == &canonical)
+        {
+            let cycle = self.loading[position..]
+                .iter()
+                .map(|(path, _)| path)
+                .chain([&canonical])
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let mut diagnostic = errors::raise("E0020", path.span.clone())
+                .with_notes(vec![format!("import cycle: {cycle}")]);
+            if let Some((_, Some(span))) = self.loading.get(position + 1) {
+                diagnostic.labels.push(errors::secondary(
+                    span.clone(),
+                    "cycle starts from this import",
+                ));
+            }
+            return Err(vec![diagnostic]);
+        }
+        if let Some(module) = self.cache.get(&canonical) {
+            return Ok(*module);
+        }
+
+        let source = match fs::read_to_string(&canonical) {
+            Ok(source) => source,
+            Err(error) => {
+                let diagnostic = errors::raise("E0019", path.span.clone())
+                    .with_notes(vec![format!("{}: {error}", canonical.display())]);
+                return Err(vec![diagnostic]);
+            }
+        };
+        let name = canonical.display().to_string();
+        let imported = self.sources.add(name.clone(), source);
+        let directory = canonical.parent().unwrap().to_path_buf();
+
+        self.loading
+            .push((canonical.clone(), Some(path.span.clone())));
+        let module = self.load_module(name, imported, &directory)?;
+        self.loading.pop();
+        self.cache.insert(canonical, module);
+        Ok(module)
+    }
+
+    fn emit(self) -> Bytecode {
+        Bytecode {
+            modules: self.modules.into_iter().map(Option::unwrap).collect(),
+        }
+    }
+}
+
+// Load the main script from `file`, along with all modules it imports.
+//
+// Warnings are collected into `warnings` whether the loading succeeds or not.
+//
+// `path` is where the main script is read from, or `None` if it's not from a file (e.g.
+// REPL), in which case imports are resolved relative to the working directory.
+pub fn load(
+    sources: &mut SourceMap,
+    file: FileId,
+    path: Option<&Path>,
+    lints: &Lints,
+    warnings: &mut Vec<Diagnostic>,
+) -> DiagnosticsResult<Bytecode> {
+    let mut loader = Loader::new(sources, lints, warnings);
+    let (name, directory) = match path.and_then(|path| path.canonicalize().ok()) {
+        Some(canonical) => {
+            loader.loading.push((canonical.clone(), None));
+            let directory = canonical.parent().unwrap().to_path_buf();
+            (canonical.display().to_string(), directory)
+        }
+        None => (String::from("<script>"), PathBuf::from(".")),
+    };
+    loader.load_module(name, file, &directory)?;
+    let bytecode = loader.emit();
+
+    #[cfg(feature = "bytecode-preview")]
+    {
+        println!("━━━━━━━━━━ Bytecode Preview Start ━━━━━━━━━━");
+        print!("{}", bytecode.disassemble(sources));
+    }
+
+    Ok(bytecode)
+}
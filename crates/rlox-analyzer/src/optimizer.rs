@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use rlox_intermediate::*;
+
+// Absolute target of a jump at `index`, which may be the end of the chunk.
+fn jump_target(index: usize, instruction: &Instruction) -> Option<usize> {
+    let offset = instruction.offset()?;
+    Some((index as isize + offset) as usize)
+}
+
+fn retarget(index: usize, instruction: &mut Instruction, target: usize) {
+    *instruction.offset_mut().unwrap() = target as isize - index as isize;
+}
+
+// Indices where control may arrive other than from the previous instruction, which are
+// never fused with the previous one. Boundaries of handlers count as well, so that
+// instructions never move into or out of try clauses.
+fn labels(chunk: &ChunkBuilder) -> HashSet<usize> {
+    let mut labels = chunk
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| jump_target(index, instruction))
+        .collect::<HashSet<_>>();
+    for handler in &chunk.handlers {
+        labels.extend([handler.start, handler.end, handler.target]);
+    }
+    labels
+}
+
+// Remove instructions not kept, with jump offsets and handlers rewritten to the new indices.
+// Jumps to a removed instruction land on the next kept one.
+fn compact(chunk: &mut ChunkBuilder, keep: &[bool]) {
+    // new indices of every instruction, and of the end of the chunk.
+    let mut indices = Vec::with_capacity(keep.len() + 1);
+    let mut next = 0;
+    for kept in keep {
+        indices.push(next);
+        next += *kept as usize;
+    }
+    indices.push(next);
+
+    let instructions = std::mem::take(&mut chunk.instructions);
+    let spans = std::mem::take(&mut chunk.spans);
+    for (index, (mut instruction, span)) in instructions.into_iter().zip(spans).enumerate() {
+        if !keep[index] {
+            continue;
+        }
+        if let Some(target) = jump_target(index, &instruction) {
+            retarget(indices[index], &mut instruction, indices[target]);
+        }
+        chunk.instructions.push(instruction);
+        chunk.spans.push(span);
+    }
+    for handler in &mut chunk.handlers {
+        handler.start = indices[handler.start];
+        handler.end = indices[handler.end];
+        handler.target = indices[handler.target];
+    }
+}
+
+// Fuse `Equal, Not` into `NotEqual`, and `JumpIfFalse, Pop` with a `Pop` at its target
+// into `PopJumpIfFalse` past that `Pop`. The `Pop` at the target is left in place, since it
+// may still be reached otherwise (e.g. `a and b;` falls through to it), and it's removed
+// by dead code elimination only if it's never reached.
+//
+// Note that `Less, Not` is not the same as `GreaterEqual`, which is false for NaN.
+fn fuse(chunk: &mut ChunkBuilder) -> bool {
+    let labels = labels(chunk);
+    let mut keep = vec![true; chunk.instructions.len()];
+    let mut index = 0;
+    while index + 1 < chunk.instructions.len() {
+        if labels.contains(&(index + 1)) {
+            index += 1;
+            continue;
+        }
+        let fused = match (&chunk.instructions[index], &chunk.instructions[index + 1]) {
+            (Instruction::Equal, Instruction::Not) => Instruction::NotEqual,
+            (Instruction::JumpIfFalse(offset), Instruction::Pop) => {
+                let target = (index as isize + offset) as usize;
+                match chunk.instructions.get(target) {
+                    Some(Instruction::Pop) => Instruction::PopJumpIfFalse(offset + 1),
+                    _ => {
+                        index += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        chunk.instructions[index] = fused;
+        keep[index + 1] = false;
+        index += 2;
+    }
+    let fused = keep.contains(&false);
+    compact(chunk, &keep);
+    fused
+}
+
+// Jump chains are threaded, so that a jump lands on the final target directly.
+fn thread_jumps(chunk: &mut ChunkBuilder) {
+    for index in 0..chunk.instructions.len() {
+        let Some(mut target) = jump_target(index, &chunk.instructions[index]) else {
+            continue;
+        };
+        // jumps in a loop never end, which leave those at the second visit untouched.
+        let mut visited = HashSet::from([index]);
+        while visited.insert(target) {
+            let next = match (&chunk.instructions[index], chunk.instructions.get(target)) {
+                (_, Some(jump @ Instruction::Jump(_))) => jump_target(target, jump),
+                // the condition is still on the stack, which is false for the next one too.
+                (Instruction::JumpIfFalse(_), Some(jump @ Instruction::JumpIfFalse(_))) => {
+                    jump_target(target, jump)
+                }
+                _ => None,
+            };
+            match next {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+        retarget(index, &mut chunk.instructions[index], target);
+    }
+}
+
+// Remove instructions never reached from the entry or a handler, along with jumps to the
+// next instruction.
+fn eliminate_dead_code(chunk: &mut ChunkBuilder) -> bool {
+    let length = chunk.instructions.len();
+    let mut reachable = vec![false; length];
+    let mut pending = vec![0];
+    pending.extend(chunk.handlers.iter().map(|handler| handler.target));
+    while let Some(index) = pending.pop() {
+        if index >= length || reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+        let instruction = &chunk.instructions[index];
+        pending.extend(jump_target(index, instruction));
+        if !matches!(
+            instruction,
+            Instruction::Jump(_) | Instruction::Return | Instruction::Throw
+        ) {
+            pending.push(index + 1);
+        }
+    }
+    // a jump is a no-op if only dead instructions are skipped over.
+    let mut keep = reachable;
+    for index in 0..length {
+        if let (true, Instruction::Jump(_)) = (keep[index], &chunk.instructions[index]) {
+            let target = jump_target(index, &chunk.instructions[index]).unwrap();
+            if target > index && (index + 1..target).all(|skipped| !keep[skipped]) {
+                keep[index] = false;
+            }
+        }
+    }
+    let eliminated = keep.contains(&false);
+    compact(chunk, &keep);
+    eliminated
+}
+
+// Rewrite patterns emitted by the compiler into shorter sequences, which behave the same.
+pub fn optimize(chunk: &mut ChunkBuilder) {
+    loop {
+        let fused = fuse(chunk);
+        thread_jumps(chunk);
+        let eliminated = eliminate_dead_code(chunk);
+        if !fused && !eliminated {
+            break;
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use rlox_intermediate::*;
 
-use crate::scanner::Token;
+use crate::scanner::{Lexeme, Token};
 
 mod declaration;
 mod expression;
@@ -10,13 +10,113 @@ mod utility;
 struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // errors recovered from, see `Parser::recover`.
+    diagnostics: Vec<Diagnostic>,
 }
 
-pub fn parse(tokens: Vec<Token>) -> DiagnosableResult<Vec<Declaration>> {
-    let mut parser = Parser::new(tokens);
+// Lexical errors reported by the scanner are passed along, so that all errors are
+// returned together.
+pub fn parse(
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
+) -> DiagnosticsResult<Vec<Declaration>> {
+    let mut parser = Parser::new(tokens, diagnostics);
     let mut declarations = Vec::new();
     while !parser.has_reached_end() {
-        declarations.push(parser.parse_declaration()?);
+        match parser.recover(Parser::parse_top_level_declaration) {
+            Ok(Some(declaration)) => declarations.push(declaration),
+            Ok(None) => {}
+            // there's nothing left to recover at EOF.
+            Err(diagnostic) if !parser.is_caused_by_error_token(&diagnostic) => {
+                parser.diagnostics.push(*diagnostic)
+            }
+            Err(_) => {}
+        }
+    }
+    if !parser.diagnostics.is_empty() {
+        // lexical errors come first, so sort them all in the order of source.
+        let mut diagnostics = parser.diagnostics;
+        diagnostics
+            .sort_by_key(|diagnostic| diagnostic.labels.first().map(|label| label.range.start));
+        return Err(diagnostics);
     }
     Ok(declarations)
 }
+
+impl Parser {
+    fn parse_top_level_declaration(&mut self) -> DiagnosableResult<Declaration> {
+        // imports are only allowed at top level.
+        if let Lexeme::Import = self.must_peek()?.value {
+            return self.parse_import_declaration();
+        }
+        self.parse_declaration()
+    }
+
+    // Panic mode: if `parse` fails, the diagnostic is recorded and tokens are skipped until
+    // the next declaration boundary, so that parsing goes on and more errors are reported
+    // at once. `None` is returned for the skipped declaration.
+    //
+    // Errors at EOF are still returned, and recorded by `parse` only once, since every
+    // unclosed block would otherwise report the same early EOF.
+    pub(super) fn recover<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> DiagnosableResult<T>,
+    ) -> DiagnosableResult<Option<T>> {
+        let start = self.current;
+        match parse(self) {
+            Ok(value) => Ok(Some(value)),
+            Err(diagnostic) if self.has_reached_end() => Err(diagnostic),
+            Err(diagnostic) => {
+                if !self.is_caused_by_error_token(&diagnostic) {
+                    self.diagnostics.push(*diagnostic);
+                }
+                self.synchronize(start);
+                Ok(None)
+            }
+        }
+    }
+
+    // Error tokens are already reported by the scanner, it's no use to report that they
+    // are unexpected again.
+    fn is_caused_by_error_token(&self, diagnostic: &Diagnostic) -> bool {
+        diagnostic.labels.first().is_some_and(|label| {
+            self.tokens.iter().any(|token| {
+                token.value == Lexeme::Error
+                    && token.span.file == label.file_id
+                    && token.span.range == label.range
+            })
+        })
+    }
+
+    // Skip tokens until a semicolon is passed, or a token which may start a declaration (or
+    // open/close a block) is reached.
+    fn synchronize(&mut self, start: usize) {
+        // always make progress, or the same error will be reported again and again.
+        if self.current == start {
+            self.advance();
+        }
+        while let Some(token) = self.peek() {
+            if self.tokens[self.current - 1].value == Lexeme::Semicolon {
+                return;
+            }
+            match token.value {
+                Lexeme::Class
+                | Lexeme::Fun
+                | Lexeme::Var
+                | Lexeme::For
+                | Lexeme::If
+                | Lexeme::While
+                | Lexeme::Print
+                | Lexeme::Return
+                | Lexeme::Import
+                | Lexeme::Throw
+                | Lexeme::Try
+                | Lexeme::LeftBrace
+                | Lexeme::RightBrace => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+}
@@ -4,12 +4,14 @@ use crate::parser::Parser;
 use crate::scanner::{Lexeme, Token};
 
 static IDENTIFIER: Lexeme = Lexeme::Identifier(String::new());
+static STRING: Lexeme = Lexeme::String(String::new());
 
 impl Parser {
     pub fn parse_declaration(&mut self) -> DiagnosableResult<Declaration> {
         Ok(match &self.must_peek()?.value {
             Lexeme::Class => self.parse_class_declaration()?,
-            Lexeme::Fun => self.parse_fun_declaration()?,
+            // "fun" followed by a left parenthesis is a lambda expression.
+            Lexeme::Fun if !self.is_lambda() => self.parse_fun_declaration()?,
             Lexeme::Var => self.parse_var_declaration()?,
             _ => Declaration::Statement(self.parse_statement()?),
         })
@@ -43,38 +45,59 @@ impl Parser {
         self.parse_fun_item()
     }
 
+    pub(super) fn parse_import_declaration(&mut self) -> DiagnosableResult<Declaration> {
+        self.must_consume(&Lexeme::Import)?;
+        let Token { value, span } = self.must_consume(&STRING)?;
+        let path = match value {
+            Lexeme::String(path) => Spanned::new(path.clone(), span.clone()),
+            _ => unreachable!("incorrect token from must_consume"),
+        };
+        self.must_consume(&Lexeme::As)?;
+        let name = self.must_consume_identifier()?;
+        self.must_consume(&Lexeme::Semicolon)?;
+        Ok(Declaration::Import { path, name })
+    }
+
     // also needed by "for" statement.
     pub(super) fn parse_var_declaration(&mut self) -> DiagnosableResult<Declaration> {
         self.must_consume(&Lexeme::Var)?;
         let name = self.must_consume_identifier()?;
+        let annotation = self.parse_annotation()?;
         let initializer = if self.try_consume(&Lexeme::Equal) {
             Some(self.parse_expression()?)
         } else {
             None
         };
         self.must_consume(&Lexeme::Semicolon)?;
-        Ok(Declaration::Var { name, initializer })
+        Ok(Declaration::Var {
+            name,
+            annotation,
+            initializer,
+        })
     }
 }
 
 // Utility rules
 type Parameters = Vec<Spanned<String>>;
+type ParameterTypes = Vec<Option<Spanned<Type>>>;
 type Arguments = Vec<Expression>;
 
 // Utility functions
 impl Parser {
     fn try_consume_identifier(&mut self) -> Option<Spanned<String>> {
-        if let Some(Token { value, span }) = self.peek() {
-            if let Lexeme::Identifier(identifier) = value {
-                let identifier = Spanned::new(identifier.clone(), span.clone());
-                self.advance();
-                return Some(identifier);
-            }
+        if let Some(Token {
+            value: Lexeme::Identifier(identifier),
+            span,
+        }) = self.peek()
+        {
+            let identifier = Spanned::new(identifier.clone(), span.clone());
+            self.advance();
+            return Some(identifier);
         }
         None
     }
 
-    fn must_consume_identifier(&mut self) -> DiagnosableResult<Spanned<String>> {
+    pub(super) fn must_consume_identifier(&mut self) -> DiagnosableResult<Spanned<String>> {
         let Token { value, span } = self.must_consume(&IDENTIFIER)?;
         if let Lexeme::Identifier(identifier) = value {
             return Ok(Spanned::new(identifier.clone(), span.clone()));
@@ -89,26 +112,54 @@ impl Parser {
     fn parse_fun_item(&mut self) -> DiagnosableResult<Declaration> {
         let name = self.must_consume_identifier()?;
         self.must_consume(&Lexeme::LeftParenthesis)?;
-        let parameters = self.parse_parameters()?;
+        let (parameters, parameter_types) = self.parse_parameters()?;
         self.must_consume(&Lexeme::RightParenthesis)?;
+        let return_type = self.parse_annotation()?;
         let body = Box::new(self.parse_block_statement()?);
         Ok(Declaration::Function {
             name,
             parameters,
+            parameter_types,
+            return_type,
             body,
         })
     }
 
     // Zero or more identifiers separated by comma.
-    fn parse_parameters(&mut self) -> DiagnosableResult<Parameters> {
+    // Each parameter may be annotated with a type, e.g. `a: number`.
+    pub(super) fn parse_parameters(&mut self) -> DiagnosableResult<(Parameters, ParameterTypes)> {
         let mut parameters = Parameters::new();
+        let mut parameter_types = ParameterTypes::new();
         if let Some(identifier) = self.try_consume_identifier() {
             parameters.push(identifier);
+            parameter_types.push(self.parse_annotation()?);
         }
         while self.try_consume(&Lexeme::Comma) {
             parameters.push(self.must_consume_identifier()?);
+            parameter_types.push(self.parse_annotation()?);
         }
-        Ok(parameters)
+        Ok((parameters, parameter_types))
+    }
+
+    // Optional type annotation after a colon.
+    pub(super) fn parse_annotation(&mut self) -> DiagnosableResult<Option<Spanned<Type>>> {
+        if !self.try_consume(&Lexeme::Colon) {
+            return Ok(None);
+        }
+        let Token { value, span } = self.must_advance()?;
+        let annotation = match value {
+            Lexeme::Identifier(name) => match name.as_str() {
+                "any" => Type::Any,
+                "bool" => Type::Boolean,
+                "number" => Type::Number,
+                "string" => Type::String,
+                _ => raise!("E0027", span.clone()),
+            },
+            Lexeme::Nil => Type::Nil,
+            Lexeme::Fun => Type::Function,
+            _ => raise!("E0027", span.clone()),
+        };
+        Ok(Some(Spanned::new(annotation, span.clone())))
     }
 
     // Zero or more expressions separated by comma.
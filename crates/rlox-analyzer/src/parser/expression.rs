@@ -11,6 +11,7 @@ use crate::scanner::{Lexeme, Token};
 enum Precedence {
     None,
     Assignment,     // right associative
+    Ternary,        // right associative
     ConditionalOr,  // left associative
     ConditionalAnd, // left associative
     Equality,       // left associative
@@ -26,7 +27,7 @@ impl Precedence {
     pub fn increase(&self) -> Self {
         let number = *self as u8;
         if number < Self::Impossible as u8 {
-            unsafe { mem::transmute(number + 1) }
+            unsafe { mem::transmute::<u8, Precedence>(number + 1) }
         } else {
             Self::Impossible
         }
@@ -36,7 +37,13 @@ impl Precedence {
 impl Lexeme {
     fn precedence(&self) -> Precedence {
         match self {
-            Lexeme::Equal => Precedence::Assignment,
+            Lexeme::Equal
+            | Lexeme::PlusEqual
+            | Lexeme::MinusEqual
+            | Lexeme::StarEqual
+            | Lexeme::SlashEqual
+            | Lexeme::PercentEqual => Precedence::Assignment,
+            Lexeme::Question => Precedence::Ternary,
             Lexeme::Or => Precedence::ConditionalOr,
             Lexeme::And => Precedence::ConditionalAnd,
             Lexeme::EqualEqual | Lexeme::BangEqual => Precedence::Equality,
@@ -44,8 +51,10 @@ impl Lexeme {
                 Precedence::Relational
             }
             Lexeme::Plus | Lexeme::Minus => Precedence::Additive,
-            Lexeme::Star | Lexeme::Slash => Precedence::Multiplicative,
-            Lexeme::LeftParenthesis => Precedence::Invocation,
+            Lexeme::Star | Lexeme::Slash | Lexeme::Percent => Precedence::Multiplicative,
+            Lexeme::LeftParenthesis | Lexeme::PlusPlus | Lexeme::MinusMinus => {
+                Precedence::Invocation
+            }
             Lexeme::Dot => Precedence::Property,
             _ => Precedence::None,
         }
@@ -80,10 +89,15 @@ impl Parser {
             Lexeme::Identifier(identifier) => literal!(Identifier, identifier),
             Lexeme::This => literal!(This),
             Lexeme::Super => literal!(Super),
+            // lambda
+            Lexeme::Fun => self.parse_lambda()?,
+            Lexeme::LeftParenthesis if self.is_arrow_lambda() => self.parse_arrow_lambda()?,
             // parenthesized
             Lexeme::LeftParenthesis => self.parse_parenthesized()?,
             // unary
             Lexeme::Bang | Lexeme::Minus => self.parse_unary()?,
+            // prefix increment / decrement
+            Lexeme::PlusPlus | Lexeme::MinusMinus => self.parse_prefix_update()?,
             _ => raise!("E0004", span),
         };
 
@@ -93,7 +107,14 @@ impl Parser {
             }
             expression = match infix.deref() {
                 Lexeme::LeftParenthesis => self.parse_invocation(expression)?,
-                Lexeme::Equal => self.parse_assignment(expression)?,
+                Lexeme::PlusPlus | Lexeme::MinusMinus => self.parse_postfix_update(expression)?,
+                Lexeme::Equal
+                | Lexeme::PlusEqual
+                | Lexeme::MinusEqual
+                | Lexeme::StarEqual
+                | Lexeme::SlashEqual
+                | Lexeme::PercentEqual => self.parse_assignment(expression)?,
+                Lexeme::Question => self.parse_ternary(expression)?,
                 _ => self.parse_binary(expression)?,
             };
         }
@@ -107,6 +128,108 @@ impl Parser {
         Ok(expression)
     }
 
+    fn parse_lambda(&mut self) -> DiagnosableResult<Expression> {
+        let span = self.must_consume(&Lexeme::Fun)?.span.clone();
+        self.must_consume(&Lexeme::LeftParenthesis)?;
+        let (parameters, parameter_types) = self.parse_parameters()?;
+        self.must_consume(&Lexeme::RightParenthesis)?;
+        let return_type = self.parse_annotation()?;
+        let body = Box::new(self.parse_block_statement()?);
+        Ok(Expression::Lambda {
+            span,
+            parameters,
+            parameter_types,
+            return_type,
+            body,
+        })
+    }
+
+    // Arrow lambdas are written as `(a, b) => a + b` or `(a, b) => { return a + b; }`.
+    //
+    // parameters may be annotated, but the return type can't be, which would be confused
+    // with ternary expressions.
+    fn parse_arrow_lambda(&mut self) -> DiagnosableResult<Expression> {
+        self.must_consume(&Lexeme::LeftParenthesis)?;
+        let (parameters, parameter_types) = self.parse_parameters()?;
+        self.must_consume(&Lexeme::RightParenthesis)?;
+        let span = self.must_consume(&Lexeme::FatArrow)?.span.clone();
+        let body = if let Lexeme::LeftBrace = self.must_peek()?.value {
+            self.parse_block_statement()?
+        } else {
+            Statement::Return {
+                span: span.clone(),
+                expression: Some(self.parse_expression()?),
+            }
+        };
+        Ok(Expression::Lambda {
+            span,
+            parameters,
+            parameter_types,
+            return_type: None,
+            body: Box::new(body),
+        })
+    }
+
+    pub(super) fn is_lambda(&self) -> bool {
+        matches!(
+            self.peek_nth(1),
+            Some(Token {
+                value: Lexeme::LeftParenthesis,
+                ..
+            })
+        )
+    }
+
+    // Lookahead for `(identifier, ...) =>` without consuming anything, which tells arrow
+    // lambdas from parenthesized expressions.
+    fn is_arrow_lambda(&self) -> bool {
+        let mut n = 1;
+        if let Some(Token {
+            value: Lexeme::Identifier(_),
+            ..
+        }) = self.peek_nth(n)
+        {
+            n = self.skip_annotation(n + 1);
+            while let Some(Token {
+                value: Lexeme::Comma,
+                ..
+            }) = self.peek_nth(n)
+            {
+                match self.peek_nth(n + 1) {
+                    Some(Token {
+                        value: Lexeme::Identifier(_),
+                        ..
+                    }) => n = self.skip_annotation(n + 2),
+                    _ => return false,
+                }
+            }
+        }
+        matches!(
+            (self.peek_nth(n), self.peek_nth(n + 1)),
+            (
+                Some(Token {
+                    value: Lexeme::RightParenthesis,
+                    ..
+                }),
+                Some(Token {
+                    value: Lexeme::FatArrow,
+                    ..
+                })
+            )
+        )
+    }
+
+    // types are single tokens, so an annotation is a colon followed by one token.
+    fn skip_annotation(&self, n: usize) -> usize {
+        match self.peek_nth(n) {
+            Some(Token {
+                value: Lexeme::Colon,
+                ..
+            }) => n + 2,
+            _ => n,
+        }
+    }
+
     fn parse_invocation(&mut self, left: Expression) -> DiagnosableResult<Expression> {
         self.must_consume(&Lexeme::LeftParenthesis)?;
         let arguments = self.parse_arguments()?;
@@ -120,7 +243,8 @@ impl Parser {
     fn parse_unary(&mut self) -> DiagnosableResult<Expression> {
         #[rustfmt::skip]
         let Token { value: operator, span } = self.must_advance()?.clone();
-        let expression = self.parse_precedence(Precedence::Property)?;
+        // invocations and postfix updates bind tighter, e.g. `-a++` is `-(a++)`.
+        let expression = self.parse_precedence(Precedence::Invocation)?;
         Ok(match operator {
             Lexeme::Bang => Expression::Unary {
                 operator: Spanned::new(UnaryOperator::Not, span.clone()),
@@ -134,18 +258,78 @@ impl Parser {
         })
     }
 
+    fn parse_prefix_update(&mut self) -> DiagnosableResult<Expression> {
+        #[rustfmt::skip]
+        let Token { value: operator, span } = self.must_advance()?.clone();
+        let expression = self.parse_precedence(Precedence::Invocation)?;
+        Ok(Expression::Update {
+            operator: Spanned::new(Self::update_operator(&operator), span),
+            prefix: true,
+            expression: Box::new(expression),
+        })
+    }
+
+    fn parse_postfix_update(&mut self, left: Expression) -> DiagnosableResult<Expression> {
+        #[rustfmt::skip]
+        let Token { value: operator, span } = self.must_advance()?.clone();
+        Ok(Expression::Update {
+            operator: Spanned::new(Self::update_operator(&operator), span),
+            prefix: false,
+            expression: Box::new(left),
+        })
+    }
+
+    fn update_operator(operator: &Lexeme) -> UpdateOperator {
+        match operator {
+            Lexeme::PlusPlus => UpdateOperator::Increment,
+            Lexeme::MinusMinus => UpdateOperator::Decrement,
+            _ => unreachable!("incorrect update operator forwarded from precedence parsing"),
+        }
+    }
+
     fn parse_assignment(&mut self, left: Expression) -> DiagnosableResult<Expression> {
         #[rustfmt::skip]
         let Token { value: operator, span } = self.must_advance()?.clone();
         let precedence = operator.precedence();
         let right = self.parse_precedence(precedence)?;
-        Ok(Expression::Assignment {
+        // compound assignments are kept in AST and desugared by the compiler.
+        let operator = match operator {
+            Lexeme::Equal => {
+                return Ok(Expression::Assignment {
+                    left: Box::new(left),
+                    span,
+                    right: Box::new(right),
+                })
+            }
+            Lexeme::PlusEqual => BinaryOperator::Add,
+            Lexeme::MinusEqual => BinaryOperator::Subtract,
+            Lexeme::StarEqual => BinaryOperator::Multiply,
+            Lexeme::SlashEqual => BinaryOperator::Divide,
+            Lexeme::PercentEqual => BinaryOperator::Modulo,
+            _ => unreachable!("incorrect assignment operator forwarded from precedence parsing"),
+        };
+        Ok(Expression::CompoundAssignment {
             left: Box::new(left),
-            span,
+            operator: Spanned::new(operator, span),
             right: Box::new(right),
         })
     }
 
+    fn parse_ternary(&mut self, condition: Expression) -> DiagnosableResult<Expression> {
+        #[rustfmt::skip]
+        let Token { value: operator, span } = self.must_advance()?.clone();
+        // anything can be placed between "?" and ":", just like a parenthesized expression.
+        let then = self.parse_expression()?;
+        self.must_consume(&Lexeme::Colon)?;
+        let otherwise = self.parse_precedence(operator.precedence())?;
+        Ok(Expression::Ternary {
+            condition: Box::new(condition),
+            span,
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        })
+    }
+
     fn parse_binary(&mut self, left: Expression) -> DiagnosableResult<Expression> {
         #[rustfmt::skip]
         let Token { value: operator, span } = self.must_advance()?.clone();
@@ -164,6 +348,7 @@ impl Parser {
             Lexeme::Minus => BinaryOperator::Subtract,
             Lexeme::Star => BinaryOperator::Multiply,
             Lexeme::Slash => BinaryOperator::Divide,
+            Lexeme::Percent => BinaryOperator::Modulo,
             Lexeme::Dot => BinaryOperator::PropertyAccess,
             _ => unreachable!("incorrect binary operator forwarded from precedence parsing"),
         };
@@ -10,6 +10,8 @@ impl Parser {
             Lexeme::If => self.parse_if_statement()?,
             Lexeme::Print => self.parse_print_statement()?,
             Lexeme::Return => self.parse_return_statement()?,
+            Lexeme::Throw => self.parse_throw_statement()?,
+            Lexeme::Try => self.parse_try_statement()?,
             Lexeme::While => self.parse_while_statement()?,
             Lexeme::LeftBrace => self.parse_block_statement()?,
             _ => self.parse_expression_statement()?,
@@ -96,14 +98,52 @@ impl Parser {
     }
 
     fn parse_return_statement(&mut self) -> DiagnosableResult<Statement> {
-        self.must_consume(&Lexeme::Return)?;
-        let expression = if self.try_consume(&Lexeme::Semicolon) {
+        let span = self.must_consume(&Lexeme::Return)?.span.clone();
+        if self.try_consume(&Lexeme::Semicolon) {
+            return Ok(Statement::Return {
+                span,
+                expression: None,
+            });
+        }
+        let expression = Some(self.parse_expression()?);
+        self.must_consume(&Lexeme::Semicolon)?;
+        Ok(Statement::Return { span, expression })
+    }
+
+    fn parse_throw_statement(&mut self) -> DiagnosableResult<Statement> {
+        let span = self.must_consume(&Lexeme::Throw)?.span.clone();
+        let expression = self.parse_expression()?;
+        self.must_consume(&Lexeme::Semicolon)?;
+        Ok(Statement::Throw { span, expression })
+    }
+
+    // both catch and finally clauses are optional, but at least one of them is required.
+    fn parse_try_statement(&mut self) -> DiagnosableResult<Statement> {
+        let span = self.must_consume(&Lexeme::Try)?.span.clone();
+        let body = Box::new(self.parse_block_statement()?);
+        let catch = if self.try_consume(&Lexeme::Catch) {
+            self.must_consume(&Lexeme::LeftParenthesis)?;
+            let name = self.must_consume_identifier()?;
+            self.must_consume(&Lexeme::RightParenthesis)?;
+            Some((name, Box::new(self.parse_block_statement()?)))
+        } else {
             None
+        };
+        let finally = if self.try_consume(&Lexeme::Finally) {
+            Some(Box::new(self.parse_block_statement()?))
         } else {
-            Some(self.parse_expression()?)
+            None
         };
-        self.must_consume(&Lexeme::Semicolon)?;
-        Ok(Statement::Return(expression))
+        if catch.is_none() && finally.is_none() {
+            // raises "expected Catch" here.
+            self.must_consume(&Lexeme::Catch)?;
+        }
+        Ok(Statement::Try {
+            span,
+            body,
+            catch,
+            finally,
+        })
     }
 
     fn parse_while_statement(&mut self) -> DiagnosableResult<Statement> {
@@ -120,7 +160,9 @@ impl Parser {
         self.must_consume(&Lexeme::LeftBrace)?;
         let mut declarations = Vec::new();
         while !self.try_consume(&Lexeme::RightBrace) {
-            declarations.push(self.parse_declaration()?);
+            if let Some(declaration) = self.recover(Self::parse_declaration)? {
+                declarations.push(declaration);
+            }
         }
         // note that right brace is consumed in while condition.
         Ok(Statement::Block(declarations))
@@ -7,8 +7,12 @@ use crate::scanner::{Lexeme, Token};
 
 // Utility functions
 impl Parser {
-    pub(super) fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    pub(super) fn new(tokens: Vec<Token>, diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            diagnostics,
+        }
     }
 
     pub(super) fn has_reached_end(&self) -> bool {
@@ -22,6 +26,10 @@ impl Parser {
         None
     }
 
+    pub(super) fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.current + n)
+    }
+
     pub(super) fn advance(&mut self) -> Option<&Token> {
         if self.current < self.tokens.len() {
             self.current += 1;
@@ -63,10 +71,13 @@ impl Parser {
 
     // a little bit different here: try_consume returns bool, but must_consume needs to
     // return a reference to the consumed token.
+    //
+    // a mismatched token is not consumed, so that it can start the next declaration when
+    // the parser recovers from this error.
     pub(super) fn must_consume(&mut self, lexeme: &Lexeme) -> DiagnosableResult<&Token> {
-        let token = self.must_advance()?;
+        let token = self.must_peek()?;
         if mem::discriminant(&token.value) == mem::discriminant(lexeme) {
-            return Ok(token);
+            return Ok(self.advance().unwrap());
         }
         raise! {
             "E0005", token.span.clone(),
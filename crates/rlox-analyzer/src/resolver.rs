@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use rlox_intermediate::*;
+
+#[derive(Debug, Clone)]
+pub enum Resolution {
+    // a local of the current function, declared `depth` scopes out.
+    Local { depth: usize, declaration: Span },
+    // a local of an enclosing function, which is `depth` functions out.
+    Upvalue { depth: usize, declaration: Span },
+    Global,
+}
+
+// Resolutions of identifiers, keyed by their spans.
+pub type Resolutions = HashMap<Span, Resolution>;
+
+struct Local {
+    name: Spanned<String>,
+    // false while its initializer is being resolved.
+    initialized: bool,
+}
+
+type Scope = Vec<Local>;
+
+enum FunctionKind {
+    Script,
+    Function,
+    // initializer of a class, with the span of its name.
+    Initializer(Span),
+}
+
+struct Resolver {
+    // scopes of each function, from the script to the innermost one. Top level of the
+    // script has no scope, since variables declared there are globals.
+    functions: Vec<Vec<Scope>>,
+    // kinds of each function in `functions`.
+    kinds: Vec<FunctionKind>,
+    globals: HashSet<String>,
+    resolutions: Resolutions,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Self {
+            functions: vec![Vec::new()],
+            kinds: vec![FunctionKind::Script],
+            globals: HashSet::new(),
+            resolutions: HashMap::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // Globals may be used before they are defined (e.g. in a function), so all of the top
+    // level names are collected first.
+    fn define_globals(&mut self, program: &[Declaration]) {
+        for declaration in program {
+            match declaration {
+                Declaration::Class { name, .. }
+                | Declaration::Function { name, .. }
+                | Declaration::Var { name, .. }
+                | Declaration::Import { name, .. } => {
+                    self.globals.insert(name.deref().clone());
+                }
+                Declaration::Statement(_) => {}
+            }
+        }
+        self.globals
+            .extend(NATIVE_FUNCTIONS.iter().map(|(name, _)| name.to_string()));
+    }
+
+    fn resolve_declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Class { functions, .. } => {
+                for function in functions {
+                    if let Declaration::Function {
+                        name,
+                        parameters,
+                        body,
+                        ..
+                    } = function
+                    {
+                        let kind = match name.deref().as_str() {
+                            "init" => FunctionKind::Initializer(name.span.clone()),
+                            _ => FunctionKind::Function,
+                        };
+                        self.resolve_function(parameters, body, kind);
+                    }
+                }
+            }
+            // nested functions are locals, while top-level ones are globals.
+            Declaration::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                self.declare(name, true);
+                self.resolve_function(parameters, body, FunctionKind::Function)
+            }
+            Declaration::Var {
+                name, initializer, ..
+            } => {
+                // the variable is declared before its initializer, which is not able to
+                // refer to the variable itself.
+                self.declare(name, false);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                if let Some(local) = self.scopes().last_mut().and_then(|scope| scope.last_mut()) {
+                    local.initialized = true;
+                }
+            }
+            Declaration::Import { .. } => {}
+            Declaration::Statement(statement) => self.resolve_statement(statement),
+        }
+    }
+
+    fn resolve_function(
+        &mut self,
+        parameters: &[Spanned<String>],
+        body: &Statement,
+        kind: FunctionKind,
+    ) {
+        // parameters are in the outermost scope of the function.
+        self.functions.push(vec![Vec::new()]);
+        self.kinds.push(kind);
+        for parameter in parameters {
+            self.declare(parameter, true);
+        }
+        self.resolve_statement(body);
+        self.kinds.pop();
+        self.functions.pop();
+    }
+
+    fn resolve_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) | Statement::Print(expression) => {
+                self.resolve_expression(expression)
+            }
+            Statement::For {
+                initializer,
+                condition,
+                incrementer,
+                body,
+            } => {
+                self.scopes().push(Vec::new());
+                match initializer {
+                    Some(ForLoopInitializer::VarDeclaration(declaration)) => {
+                        self.resolve_declaration(declaration)
+                    }
+                    Some(ForLoopInitializer::VarInitialization(expression)) => {
+                        self.resolve_expression(expression)
+                    }
+                    None => {}
+                }
+                for expression in [condition, incrementer].into_iter().flatten() {
+                    self.resolve_expression(expression);
+                }
+                self.resolve_statement(body);
+                self.scopes().pop();
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.resolve_statement(otherwise);
+                }
+            }
+            Statement::Return { span, expression } => {
+                match self.kinds.last().unwrap() {
+                    // there's no frame to return from.
+                    FunctionKind::Script => {
+                        self.diagnostics.push(errors::raise("E0025", span.clone()))
+                    }
+                    FunctionKind::Initializer(name) if expression.is_some() => {
+                        let label = errors::secondary(name.clone(), "initializer declared here");
+                        let mut diagnostic = errors::raise("E0026", span.clone());
+                        diagnostic.labels.push(label);
+                        self.diagnostics.push(diagnostic);
+                    }
+                    _ => {}
+                }
+                if let Some(expression) = expression {
+                    self.resolve_expression(expression);
+                }
+            }
+            Statement::Throw { expression, .. } => self.resolve_expression(expression),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.resolve_statement(body);
+                if let Some((name, body)) = catch {
+                    self.scopes().push(Vec::new());
+                    self.declare(name, true);
+                    self.resolve_statement(body);
+                    self.scopes().pop();
+                }
+                if let Some(finally) = finally {
+                    self.resolve_statement(finally);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+            }
+            Statement::Block(declarations) => {
+                self.scopes().push(Vec::new());
+                for declaration in declarations {
+                    self.resolve_declaration(declaration);
+                }
+                self.scopes().pop();
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Assignment { left, right, .. } => {
+                self.resolve_expression(right);
+                match left.deref() {
+                    // assigning to an undefined global is only a warning (W0006).
+                    Expression::Literal(Spanned {
+                        value: Literal::Identifier(identifier),
+                        span,
+                    }) => self.resolve_identifier(identifier, span, false),
+                    left => self.resolve_expression(left),
+                }
+            }
+            Expression::Ternary {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_expression(then);
+                self.resolve_expression(otherwise);
+            }
+            Expression::CompoundAssignment { left, right, .. } => {
+                self.resolve_expression(right);
+                self.resolve_expression(left);
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.resolve_expression(left);
+                // the right operand of property access is a name, rather than a variable.
+                if !matches!(operator.deref(), BinaryOperator::PropertyAccess) {
+                    self.resolve_expression(right);
+                }
+            }
+            Expression::Unary { expression, .. } | Expression::Update { expression, .. } => {
+                self.resolve_expression(expression)
+            }
+            Expression::Invocation {
+                expression,
+                arguments,
+            } => {
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+                self.resolve_expression(expression);
+            }
+            Expression::Lambda {
+                parameters, body, ..
+            } => self.resolve_function(parameters, body, FunctionKind::Function),
+            Expression::Literal(literal) => {
+                if let Literal::Identifier(identifier) = literal.deref() {
+                    self.resolve_identifier(identifier, &literal.span, true);
+                }
+            }
+        }
+    }
+
+    fn resolve_identifier(&mut self, identifier: &str, span: &Span, read: bool) {
+        let mut found = None;
+        'search: for (function_depth, scopes) in self.functions.iter().rev().enumerate() {
+            for (depth, scope) in scopes.iter().rev().enumerate() {
+                if let Some(local) = scope
+                    .iter()
+                    .rev()
+                    .find(|local| local.name.deref() == identifier)
+                {
+                    found = Some((function_depth, depth, local));
+                    break 'search;
+                }
+            }
+        }
+        let resolution = match found {
+            Some((0, _, local)) if !local.initialized => {
+                self.diagnostics.push(errors::raise("E0023", span.clone()));
+                return;
+            }
+            Some((0, depth, local)) => Resolution::Local {
+                depth,
+                declaration: local.name.span.clone(),
+            },
+            Some((depth, _, local)) => Resolution::Upvalue {
+                depth,
+                declaration: local.name.span.clone(),
+            },
+            None if read && !self.globals.contains(identifier) => {
+                self.diagnostics.push(errors::raise("E0012", span.clone()));
+                return;
+            }
+            None => Resolution::Global,
+        };
+        self.resolutions.insert(span.clone(), resolution);
+    }
+
+    // Variables declared at top level of the script are globals.
+    fn declare(&mut self, name: &Spanned<String>, initialized: bool) {
+        let Some(scope) = self.functions.last_mut().unwrap().last_mut() else {
+            return;
+        };
+        if let Some(local) = scope
+            .iter()
+            .find(|local| local.name.deref() == name.deref())
+        {
+            let label = errors::secondary(local.name.span.clone(), "first declared here");
+            let mut diagnostic = errors::raise("E0024", name.span.clone());
+            diagnostic.labels.push(label);
+            self.diagnostics.push(diagnostic);
+        }
+        scope.push(Local {
+            name: name.clone(),
+            initialized,
+        });
+    }
+
+    fn scopes(&mut self) -> &mut Vec<Scope> {
+        self.functions.last_mut().unwrap()
+    }
+}
+
+// Resolve every identifier of a module to the variable it refers to, which is used by
+// the compiler to locate locals.
+pub fn resolve(program: &[Declaration]) -> DiagnosticsResult<Resolutions> {
+    let mut resolver = Resolver::new();
+    resolver.define_globals(program);
+    for declaration in program {
+        resolver.resolve_declaration(declaration);
+    }
+    if !resolver.diagnostics.is_empty() {
+        return Err(resolver.diagnostics);
+    }
+    Ok(resolver.resolutions)
+}
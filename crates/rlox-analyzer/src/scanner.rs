@@ -7,7 +7,7 @@ use rlox_intermediate::*;
 #[rustfmt::skip]
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\r\n]+")]
-#[logos(error = Option<ParseFloatError>)]
+#[logos(error = LexicalError)]
 pub enum Lexeme {
     // Operators
     #[token("(")] LeftParenthesis,
@@ -19,8 +19,11 @@ pub enum Lexeme {
     #[token("-")] Minus,
     #[token("+")] Plus,
     #[token(";")] Semicolon,
+    #[token("?")] Question,
+    #[token(":")] Colon,
     #[token("/")] Slash,
     #[token("*")] Star,
+    #[token("%")] Percent,
     #[token("!")] Bang,
     #[token("=")] Equal,
     #[token(">")] Greater,
@@ -29,12 +32,21 @@ pub enum Lexeme {
     #[token("==")] EqualEqual,
     #[token(">=")] GreaterEqual,
     #[token("<=")] LessEqual,
+    #[token("=>")] FatArrow,
+    #[token("+=")] PlusEqual,
+    #[token("-=")] MinusEqual,
+    #[token("*=")] StarEqual,
+    #[token("/=")] SlashEqual,
+    #[token("%=")] PercentEqual,
+    #[token("++")] PlusPlus,
+    #[token("--")] MinusMinus,
 
     // Literals
     #[regex("[a-zA-Z][a-zA-Z0-9]*", scan_identifier)]
     Identifier(String),
 
     #[regex("\"[^\"]*\"", scan_string)]
+    #[regex("\"[^\"]*", scan_unterminated_string)]
     String(String),
 
     #[regex("[0-9]+(\\.[0-9]+)?", scan_number)]
@@ -42,17 +54,23 @@ pub enum Lexeme {
 
     // Keywords.
     #[token("and")]    And,
+    #[token("as")]     As,
     #[token("class")]  Class,
     #[token("else")]   Else,
     #[token("false")]  False,
     #[token("for")]    For,
     #[token("fun")]    Fun,
     #[token("if")]     If,
+    #[token("import")] Import,
     #[token("nil")]    Nil,
     #[token("or")]     Or,
     #[token("print")]  Print,
     #[token("return")] Return,
     #[token("super")]  Super,
+    #[token("throw")]  Throw,
+    #[token("try")]    Try,
+    #[token("catch")]  Catch,
+    #[token("finally")] Finally,
     #[token("this")]   This,
     #[token("true")]   True,
     #[token("var")]    Var,
@@ -61,6 +79,17 @@ pub enum Lexeme {
     // Comments are skipped.
     #[regex("//[^\n]*", logos::skip)]
     Comment,
+
+    // Placeholder of an invalid token, which is already reported by the scanner.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum LexicalError {
+    #[default]
+    Unrecognized,
+    UnparsableNumber(ParseFloatError),
+    UnterminatedString,
 }
 
 fn scan_identifier(lexer: &mut Lexer<Lexeme>) -> String {
@@ -72,27 +101,43 @@ fn scan_string(lexer: &mut Lexer<Lexeme>) -> String {
     (&slice[1..slice.len() - 1]).into() // Drop quotes.
 }
 
-fn scan_number(lexer: &mut Lexer<Lexeme>) -> Result<f64, ParseFloatError> {
-    lexer.slice().parse::<f64>()
+fn scan_unterminated_string(_: &mut Lexer<Lexeme>) -> Result<String, LexicalError> {
+    Err(LexicalError::UnterminatedString)
+}
+
+fn scan_number(lexer: &mut Lexer<Lexeme>) -> Result<f64, LexicalError> {
+    lexer
+        .slice()
+        .parse::<f64>()
+        .map_err(LexicalError::UnparsableNumber)
 }
 
 pub type Token = Spanned<Lexeme>;
 
-pub fn scan(source: impl AsRef<str>) -> DiagnosableResult<Vec<Token>> {
+// Invalid tokens are reported and replaced by `Lexeme::Error`, so that scanning goes on
+// and the parser can still report syntax errors of the rest.
+pub fn scan(source: impl AsRef<str>, file: FileId) -> (Vec<Token>, Vec<Diagnostic>) {
     let lexer = Lexeme::lexer(source.as_ref());
     let mut tokens = Vec::new();
-    for (lexeme, span) in lexer.spanned() {
+    let mut diagnostics = Vec::new();
+    for (lexeme, range) in lexer.spanned() {
+        let span = Span::new(file, range);
         let lexeme = match lexeme {
             Ok(lexeme) => lexeme,
-            Err(error) => match error {
-                None => raise!("E0001", span),
-                Some(error) => raise!("E0002", span, format!("internal reason: {}", error)),
-            },
+            Err(error) => {
+                diagnostics.push(match error {
+                    LexicalError::Unrecognized => errors::raise("E0001", span.clone()),
+                    LexicalError::UnparsableNumber(error) => errors::raise("E0002", span.clone())
+                        .with_notes(vec![format!("internal reason: {}", error)]),
+                    LexicalError::UnterminatedString => errors::raise("E0022", span.clone()),
+                });
+                Lexeme::Error
+            }
         };
         tokens.push(Token {
             value: lexeme,
             span,
         });
     }
-    Ok(tokens)
+    (tokens, diagnostics)
 }
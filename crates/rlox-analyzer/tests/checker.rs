@@ -0,0 +1,98 @@
+use rlox_analyzer::{checker, parser, resolver, scanner};
+
+// Codes of diagnostics reported by checking the source, along with their notes.
+fn check(source: &str) -> Vec<(String, Vec<String>)> {
+    let (tokens, diagnostics) = scanner::scan(source, 0);
+    let program = parser::parse(tokens, diagnostics).unwrap();
+    let resolutions = resolver::resolve(&program).unwrap();
+    match checker::check(&program, &resolutions) {
+        Ok(()) => Vec::new(),
+        Err(diagnostics) => diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code.unwrap(), diagnostic.notes))
+            .collect(),
+    }
+}
+
+fn mismatch(note: &str) -> Vec<(String, Vec<String>)> {
+    vec![(String::from("E0028"), vec![String::from(note)])]
+}
+
+#[test]
+fn unannotated_code_is_accepted() {
+    let source = r#"
+var x;
+fun f(a, b) { if (a) return b; }
+print f(x, 1) + 1;
+print -x;
+"#;
+    assert_eq!(check(source), Vec::new());
+}
+
+#[test]
+fn annotated_values_are_checked() {
+    assert_eq!(check("var x: number = 1; x = 2;"), Vec::new());
+    assert_eq!(
+        check(r#"var x: number = "one";"#),
+        mismatch("expected number, found string")
+    );
+    assert_eq!(
+        check(r#"fun f(x: string) {} f(1);"#),
+        mismatch("expected string, found number")
+    );
+    assert_eq!(
+        check(r#"fun f(): string { return true; }"#),
+        mismatch("expected string, found bool")
+    );
+    assert_eq!(
+        check(r#"var x: number = 1; print x + "s";"#),
+        vec![(
+            String::from("E0009"),
+            vec![String::from("found number and string")]
+        )]
+    );
+}
+
+#[test]
+fn uninitialized_variables_are_nil() {
+    assert_eq!(
+        check("var x: number; print x + 1;"),
+        mismatch("expected number, found nil as uninitialized")
+    );
+    assert_eq!(
+        check("{ var x: string; }"),
+        mismatch("expected string, found nil as uninitialized")
+    );
+    assert_eq!(check("var x: nil; var y: any; var z;"), Vec::new());
+}
+
+#[test]
+fn functions_falling_off_the_end_return_nil() {
+    let nil = mismatch("expected number, found nil returned at the end");
+    assert_eq!(check("fun g(): number { }"), nil);
+    assert_eq!(check("fun g(x): number { if (x) return 1; }"), nil);
+    assert_eq!(check("fun g(x): number { while (x) return 1; }"), nil);
+    assert_eq!(
+        check("fun g(): number { try { return 1; } catch (e) { } }"),
+        nil
+    );
+    assert_eq!(check("var g = fun (): number { };"), nil);
+    assert_eq!(check("fun g(): nil { } fun h(): any { }"), Vec::new());
+}
+
+#[test]
+fn functions_always_returning_are_accepted() {
+    let sources = [
+        "fun g(x): number { if (x) return 1; else return 2; }",
+        "fun g(x): number { if (x) return 1; throw x; }",
+        "fun g(): number { while (true) { } }",
+        "fun g(): number { for (;;) { } }",
+        "fun g(): number { { return 1; } }",
+        "fun g(): number { try { return 1; } catch (e) { return 2; } }",
+        "fun g(): number { try { } finally { return 1; } }",
+        "fun g(): number { try { return 1; } finally { print 2; } }",
+    ];
+    for source in sources {
+        assert_eq!(check(source), Vec::new(), "{source}");
+    }
+}
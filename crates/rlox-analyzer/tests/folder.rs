@@ -0,0 +1,68 @@
+use rlox_analyzer::{folder, parser, scanner};
+use rlox_intermediate::*;
+
+// The folded value of an expression statement, if it's folded at all.
+fn fold(source: &str) -> Option<String> {
+    let (tokens, diagnostics) = scanner::scan(format!("{source};"), 0);
+    let program = parser::parse(tokens, diagnostics).unwrap();
+    match &program[..] {
+        [Declaration::Statement(Statement::Expression(expression))] => {
+            folder::fold(expression).map(|literal| format!("{literal:?}"))
+        }
+        _ => panic!("{source} is not an expression"),
+    }
+}
+
+fn folded(value: &str) -> Option<String> {
+    Some(String::from(value))
+}
+
+#[test]
+fn arithmetics_are_folded() {
+    assert_eq!(fold("1 + 2 * 3"), folded("Number(7.0)"));
+    assert_eq!(fold("(1 - 4) / 2"), folded("Number(-1.5)"));
+    assert_eq!(fold("7 % 3"), folded("Number(1.0)"));
+    assert_eq!(fold("-7 % 3"), folded("Number(-1.0)"));
+    assert_eq!(fold("-(2 + 3)"), folded("Number(-5.0)"));
+    assert_eq!(fold("1 / 0"), folded("Number(inf)"));
+}
+
+#[test]
+fn strings_are_concatenated() {
+    assert_eq!(fold(r#""con" + "cat""#), folded(r#"String("concat")"#));
+    assert_eq!(fold(r#""a" + "b" + "c""#), folded(r#"String("abc")"#));
+}
+
+#[test]
+fn negations_and_comparisons_are_folded() {
+    assert_eq!(fold("!true"), folded("Boolean(false)"));
+    assert_eq!(fold("!nil"), folded("Boolean(true)"));
+    assert_eq!(fold("!0"), folded("Boolean(false)"));
+    assert_eq!(fold("1 < 2"), folded("Boolean(true)"));
+    assert_eq!(fold("2 <= 1"), folded("Boolean(false)"));
+    assert_eq!(fold("3 > 3"), folded("Boolean(false)"));
+    assert_eq!(fold("3 >= 3"), folded("Boolean(true)"));
+    assert_eq!(fold(r#""1" == 1"#), folded("Boolean(false)"));
+    assert_eq!(fold(r#""a" != "a""#), folded("Boolean(false)"));
+    assert_eq!(fold("nil == false"), folded("Boolean(false)"));
+    assert_eq!(fold("nil or 2"), folded("Number(2.0)"));
+    assert_eq!(fold("1 and nil"), folded("Nil"));
+}
+
+#[test]
+fn raising_operations_are_never_folded() {
+    let sources = [
+        r#""a" - 1"#,
+        "1 / nil",
+        r#"1 + "a""#,
+        "-true",
+        r#""a" < "b""#,
+        "nil % 2",
+        r#"1 + ("a" - 1)"#,
+    ];
+    for source in sources {
+        assert_eq!(fold(source), None, "{source}");
+    }
+    // variables are only known at runtime.
+    assert_eq!(fold("a + 1"), None);
+}
@@ -0,0 +1,157 @@
+use rlox_analyzer::optimizer;
+use rlox_intermediate::*;
+
+// A chunk of the instructions, each spanned by its index.
+fn chunk(instructions: Vec<Instruction>) -> ChunkBuilder {
+    let mut chunk = ChunkBuilder::new();
+    for (index, instruction) in instructions.into_iter().enumerate() {
+        chunk.write(instruction, Span::new(0, index..index + 1));
+    }
+    chunk
+}
+
+fn optimize(mut chunk: ChunkBuilder) -> ChunkBuilder {
+    optimizer::optimize(&mut chunk);
+    chunk
+}
+
+fn instructions(chunk: &ChunkBuilder) -> String {
+    format!("{:?}", chunk.instructions)
+}
+
+// indices of instructions before optimized, by their spans.
+fn origins(chunk: &ChunkBuilder) -> Vec<usize> {
+    chunk.spans.iter().map(|span| span.range.start).collect()
+}
+
+#[test]
+fn conditions_popped_at_targets_are_fused() {
+    // while (true) print nil;
+    let optimized = optimize(chunk(vec![
+        Instruction::True,
+        Instruction::JumpIfFalse(5),
+        Instruction::Pop,
+        Instruction::Nil,
+        Instruction::Print,
+        Instruction::Jump(-5),
+        Instruction::Pop,
+        Instruction::Nil,
+        Instruction::Return,
+    ]));
+    assert_eq!(
+        instructions(&optimized),
+        "[True, PopJumpIfFalse(4), Nil, Print, Jump(-4), Nil, Return]"
+    );
+    assert_eq!(origins(&optimized), [0, 1, 3, 4, 5, 7, 8]);
+
+    // true and false; falls through to the `Pop` at the target, which is kept.
+    let optimized = optimize(chunk(vec![
+        Instruction::True,
+        Instruction::JumpIfFalse(3),
+        Instruction::Pop,
+        Instruction::False,
+        Instruction::Pop,
+        Instruction::Nil,
+        Instruction::Return,
+    ]));
+    assert_eq!(
+        instructions(&optimized),
+        "[True, PopJumpIfFalse(3), False, Pop, Nil, Return]"
+    );
+}
+
+#[test]
+fn jump_chains_are_threaded() {
+    let optimized = optimize(chunk(vec![
+        Instruction::True,
+        Instruction::PopJumpIfFalse(3),
+        Instruction::Nil,
+        Instruction::Return,
+        Instruction::Jump(1),
+        Instruction::Jump(1),
+        Instruction::False,
+        Instruction::Return,
+    ]));
+    assert_eq!(
+        instructions(&optimized),
+        "[True, PopJumpIfFalse(3), Nil, Return, False, Return]"
+    );
+    assert_eq!(origins(&optimized), [0, 1, 2, 3, 6, 7]);
+
+    // the condition is still false at the second `JumpIfFalse`.
+    let optimized = optimize(chunk(vec![
+        Instruction::False,
+        Instruction::JumpIfFalse(2),
+        Instruction::Return,
+        Instruction::JumpIfFalse(2),
+        Instruction::Return,
+        Instruction::Return,
+    ]));
+    assert_eq!(
+        instructions(&optimized),
+        "[False, JumpIfFalse(2), Return, Return]"
+    );
+    assert_eq!(origins(&optimized), [0, 1, 2, 5]);
+}
+
+#[test]
+fn dead_code_is_eliminated() {
+    let optimized = optimize(chunk(vec![
+        Instruction::Nil,
+        Instruction::Return,
+        Instruction::Nil,
+        Instruction::Print,
+    ]));
+    assert_eq!(instructions(&optimized), "[Nil, Return]");
+
+    let optimized = optimize(chunk(vec![
+        Instruction::Nil,
+        Instruction::Throw,
+        Instruction::Nil,
+        Instruction::Return,
+    ]));
+    assert_eq!(instructions(&optimized), "[Nil, Throw]");
+
+    // jumps over dead code only are removed as well.
+    let optimized = optimize(chunk(vec![
+        Instruction::Jump(3),
+        Instruction::Nil,
+        Instruction::Print,
+        Instruction::Nil,
+        Instruction::Return,
+    ]));
+    assert_eq!(instructions(&optimized), "[Nil, Return]");
+    assert_eq!(origins(&optimized), [3, 4]);
+}
+
+#[test]
+fn handlers_are_remapped() {
+    let mut builder = chunk(vec![
+        Instruction::True,
+        Instruction::True,
+        Instruction::Equal,
+        Instruction::Not,
+        Instruction::Throw,
+        Instruction::Nil,
+        Instruction::Pop,
+        Instruction::Print,
+        Instruction::Nil,
+        Instruction::Return,
+    ]);
+    builder.handlers.push(Handler {
+        start: 0,
+        end: 5,
+        target: 7,
+        depth: 0,
+    });
+    let optimized = optimize(builder);
+    assert_eq!(
+        instructions(&optimized),
+        "[True, True, NotEqual, Throw, Print, Nil, Return]"
+    );
+    assert_eq!(origins(&optimized), [0, 1, 2, 4, 7, 8, 9]);
+    assert_eq!(
+        format!("{:?}", optimized.handlers),
+        "[Handler { start: 0, end: 4, target: 4, depth: 0 }]"
+    );
+}
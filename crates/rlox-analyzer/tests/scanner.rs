@@ -0,0 +1,48 @@
+use rlox_analyzer::scanner::{self, Lexeme};
+
+// Codes of diagnostics reported by scanning the source, along with the spanned text.
+fn diagnostics(source: &str) -> Vec<(String, String)> {
+    let (_, diagnostics) = scanner::scan(source, 0);
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| {
+            let range = diagnostic.labels[0].range.clone();
+            (diagnostic.code.unwrap(), String::from(&source[range]))
+        })
+        .collect()
+}
+
+fn lexemes(source: &str) -> Vec<Lexeme> {
+    let (tokens, _) = scanner::scan(source, 0);
+    tokens.into_iter().map(|token| token.value).collect()
+}
+
+#[test]
+fn invalid_tokens_are_replaced_by_error_tokens() {
+    assert_eq!(
+        lexemes("var a = 1 @ 2;"),
+        [
+            Lexeme::Var,
+            Lexeme::Identifier(String::from("a")),
+            Lexeme::Equal,
+            Lexeme::Number(1.0),
+            Lexeme::Error,
+            Lexeme::Number(2.0),
+            Lexeme::Semicolon,
+        ]
+    );
+    assert_eq!(lexemes("print \"open;"), [Lexeme::Print, Lexeme::Error]);
+}
+
+#[test]
+fn every_lexical_error_is_reported() {
+    assert_eq!(diagnostics("print 1;"), []);
+    assert_eq!(
+        diagnostics("var a = $;\nprint a # 1;\nprint \"open;"),
+        [
+            (String::from("E0001"), String::from("$")),
+            (String::from("E0001"), String::from("#")),
+            (String::from("E0022"), String::from("\"open;")),
+        ]
+    );
+}
@@ -1,8 +1,9 @@
+pub use annotation::*;
 pub use declaration::*;
 pub use expression::*;
 pub use statement::*;
 
+mod annotation;
 mod declaration;
 mod expression;
 mod statement;
-
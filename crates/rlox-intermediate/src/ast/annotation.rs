@@ -0,0 +1,26 @@
+use std::fmt::{Display, Formatter};
+
+// Types of optional annotations, unannotated variables are of `Type::Any`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Nil,
+    Boolean,
+    Number,
+    String,
+    Function,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Any => "any",
+            Type::Nil => "nil",
+            Type::Boolean => "bool",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Function => "fun",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Expression, Spanned, Statement};
+use crate::{Expression, Spanned, Statement, Type};
 
 #[derive(Debug)]
 pub enum Declaration {
@@ -10,11 +10,19 @@ pub enum Declaration {
     Function {
         name: Spanned<String>,
         parameters: Vec<Spanned<String>>,
+        // annotated types of parameters, in the same order.
+        parameter_types: Vec<Option<Spanned<Type>>>,
+        return_type: Option<Spanned<Type>>,
         body: Box<Statement>,
     },
     Var {
         name: Spanned<String>,
+        annotation: Option<Spanned<Type>>,
         initializer: Option<Expression>,
     },
+    Import {
+        path: Spanned<String>,
+        name: Spanned<String>,
+    },
     Statement(Statement),
 }
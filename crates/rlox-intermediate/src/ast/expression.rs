@@ -1,4 +1,4 @@
-use crate::{Span, Spanned};
+use crate::{Span, Spanned, Statement, Type};
 
 #[derive(Debug)]
 pub enum Literal {
@@ -17,6 +17,12 @@ pub enum UnaryOperator {
     Negate,
 }
 
+#[derive(Debug)]
+pub enum UpdateOperator {
+    Increment,
+    Decrement,
+}
+
 #[derive(Debug)]
 pub enum BinaryOperator {
     Assign,         // precedence: Assignment
@@ -32,6 +38,7 @@ pub enum BinaryOperator {
     Subtract,       // precedence: Additive
     Multiply,       // precedence: Multiplicative
     Divide,         // precedence: Multiplicative
+    Modulo,         // precedence: Multiplicative
     PropertyAccess, // precedence: Property
 }
 
@@ -42,6 +49,17 @@ pub enum Expression {
         span: Span,
         right: Box<Expression>,
     },
+    Ternary {
+        condition: Box<Expression>,
+        span: Span,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+    CompoundAssignment {
+        left: Box<Expression>,
+        operator: Spanned<BinaryOperator>,
+        right: Box<Expression>,
+    },
     Binary {
         left: Box<Expression>,
         operator: Spanned<BinaryOperator>,
@@ -51,9 +69,56 @@ pub enum Expression {
         operator: Spanned<UnaryOperator>,
         expression: Box<Expression>,
     },
+    Update {
+        operator: Spanned<UpdateOperator>,
+        prefix: bool,
+        expression: Box<Expression>,
+    },
     Invocation {
         expression: Box<Expression>,
         arguments: Vec<Expression>,
     },
+    Lambda {
+        span: Span,
+        parameters: Vec<Spanned<String>>,
+        parameter_types: Vec<Option<Spanned<Type>>>,
+        return_type: Option<Spanned<Type>>,
+        body: Box<Statement>,
+    },
     Literal(Spanned<Literal>),
 }
+
+impl Expression {
+    // Span covering the whole expression, except for the closing parenthesis of invocations
+    // and parenthesized expressions, which are not kept in the tree.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Assignment { left, right, .. }
+            | Expression::CompoundAssignment { left, right, .. }
+            | Expression::Binary { left, right, .. } => left.span().merge(&right.span()),
+            Expression::Ternary {
+                condition,
+                otherwise,
+                ..
+            } => condition.span().merge(&otherwise.span()),
+            Expression::Unary {
+                operator,
+                expression,
+            } => operator.span.merge(&expression.span()),
+            Expression::Update {
+                operator,
+                expression,
+                ..
+            } => operator.span.merge(&expression.span()),
+            Expression::Invocation {
+                expression,
+                arguments,
+            } => match arguments.last() {
+                Some(argument) => expression.span().merge(&argument.span()),
+                None => expression.span(),
+            },
+            Expression::Lambda { span, .. } => span.clone(),
+            Expression::Literal(literal) => literal.span.clone(),
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::ast::declaration::Declaration;
-use crate::Expression;
+use crate::{Expression, Span, Spanned};
 
 #[derive(Debug)]
 pub enum ForLoopInitializer {
@@ -22,7 +22,20 @@ pub enum Statement {
         otherwise: Option<Box<Statement>>,
     },
     Print(Expression),
-    Return(Option<Expression>),
+    Return {
+        span: Span,
+        expression: Option<Expression>,
+    },
+    Throw {
+        span: Span,
+        expression: Expression,
+    },
+    Try {
+        span: Span,
+        body: Box<Statement>,
+        catch: Option<(Spanned<String>, Box<Statement>)>,
+        finally: Option<Box<Statement>>,
+    },
     While {
         condition: Expression,
         body: Box<Statement>,
@@ -1,19 +1,62 @@
 use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 
+use crate::{FileId, Span};
+
+pub use assembly::*;
 pub use chunk::*;
+pub use encoding::*;
 pub use instruction::*;
+pub use limits::*;
+pub use serialization::*;
+pub use verifier::*;
 
+mod assembly;
 mod backpatcher;
 mod chunk;
+mod encoding;
 mod instruction;
+mod limits;
+mod serialization;
+mod verifier;
+
+// Functions provided by the VM with their arities, which are defined in every module.
+pub const NATIVE_FUNCTIONS: &[(&str, usize)] = &[("clock", 0)];
 
 pub struct Function {
+    pub name: String,
+    pub span: Span,
     pub chunk: Rc<Chunk>,
     pub arity: usize,
+    pub module: usize,
 }
 
-pub struct Bytecode {
-    pub functions: HashMap<String, Function>,
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
+
+pub struct Module {
+    pub name: String,
+    pub file: FileId,
+    pub functions: HashMap<String, Rc<Function>>,
+    // names of global slots, indexed by operands of global instructions.
+    pub globals: Vec<String>,
     pub script: Rc<Chunk>,
 }
+
+impl Module {
+    // Names of functions sorted, so that outputs never depend on the order of a hash map.
+    pub fn function_names(&self) -> Vec<&String> {
+        let mut names = self.functions.keys().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+pub struct Bytecode {
+    // the first module is the main script, others are imported by it (maybe indirectly).
+    pub modules: Vec<Module>,
+}
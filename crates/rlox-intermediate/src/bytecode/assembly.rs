@@ -0,0 +1,676 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter, Write};
+use std::rc::Rc;
+
+use codespan_reporting::files::Files;
+
+use crate::{Bytecode, Chunk, ChunkBuilder, Constant, FileId, Function, Handler, Instruction};
+use crate::{Module, SourceMap, Span, LONG_OPERAND, NATIVE_FUNCTIONS};
+
+// Assembly is a textual form of bytecode, with one directive or instruction per line and
+// comments from `;` to the end of the line:
+//
+//     .module "main"
+//     .lambda #0 "inner" 0         ; a function only referred to by constants
+//     .function #1 "outer" 1       ; a function defined in the module
+//         GetLocal 1
+//         SetGlobal "x"
+//         PopJumpIfFalse L0
+//         LoadConstant #0
+//     L0:
+//         .handler L0 L1 L2 1      ; start, end, target and depth
+//     .script                      ; the script of the module, which comes last
+//
+// Jumps refer to labels, which are local to a chunk. Constants are written inline as
+// numbers, strings or `#id` of functions defined earlier. Globals are written by names,
+// which are allocated to slots of the module in order of their first occurrences, and
+// native functions are written by their names too.
+
+// Nullary instructions by their mnemonics, which are the names of variants.
+const NULLARY: &[(&str, Instruction)] = &[
+    ("Add", Instruction::Add),
+    ("Subtract", Instruction::Subtract),
+    ("Multiply", Instruction::Multiply),
+    ("Divide", Instruction::Divide),
+    ("Modulo", Instruction::Modulo),
+    ("Negate", Instruction::Negate),
+    ("Not", Instruction::Not),
+    ("Greater", Instruction::Greater),
+    ("Less", Instruction::Less),
+    ("GreaterEqual", Instruction::GreaterEqual),
+    ("LessEqual", Instruction::LessEqual),
+    ("Equal", Instruction::Equal),
+    ("NotEqual", Instruction::NotEqual),
+    ("True", Instruction::True),
+    ("False", Instruction::False),
+    ("Nil", Instruction::Nil),
+    ("Print", Instruction::Print),
+    ("Pop", Instruction::Pop),
+    ("Duplicate", Instruction::Duplicate),
+    ("PrepareInvoke", Instruction::PrepareInvoke),
+    ("Invoke", Instruction::Invoke),
+    ("Return", Instruction::Return),
+    ("Throw", Instruction::Throw),
+];
+
+// An assembly source is rejected at the first error, with the line (from 1) it occurs.
+#[derive(Debug)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+macro_rules! invalid {
+    ($line: expr, $($arguments: tt)*) => {
+        return Err(AssemblyError {
+            line: $line,
+            message: format!($($arguments)*),
+        })
+    };
+}
+
+// Mnemonics are names of variants, without operands.
+fn mnemonic(instruction: &Instruction) -> String {
+    let name = format!("{instruction:?}");
+    name.split('(').next().unwrap().to_string()
+}
+
+struct Disassembler<'a> {
+    sources: &'a SourceMap,
+    output: String,
+    // names of global slots of the module being written.
+    globals: Vec<String>,
+    // ids of functions written, keyed by their addresses.
+    functions: HashMap<*const Function, usize>,
+}
+
+impl Disassembler<'_> {
+    fn module(&mut self, module: &Module) {
+        writeln!(self.output, ".module {:?}", module.name).unwrap();
+        self.globals = module.globals.clone();
+        let names = module
+            .function_names()
+            .into_iter()
+            .map(|name| (Rc::as_ptr(&module.functions[name]), name))
+            .collect::<HashMap<_, _>>();
+        for name in module.function_names() {
+            self.function(&module.functions[name], &names);
+        }
+        self.functions_in(&module.script, &names);
+        writeln!(self.output, "\n.script").unwrap();
+        self.chunk(&module.script);
+    }
+
+    // functions are written in post-order, so that they are defined before referred to.
+    fn functions_in(&mut self, chunk: &Chunk, names: &HashMap<*const Function, &String>) {
+        for constant in chunk.constants() {
+            if let Constant::Function(function) = constant {
+                self.function(function, names);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Rc<Function>, names: &HashMap<*const Function, &String>) {
+        let address = Rc::as_ptr(function);
+        if self.functions.contains_key(&address) {
+            return;
+        }
+        self.functions_in(&function.chunk, names);
+        let id = self.functions.len();
+        self.functions.insert(address, id);
+        let (directive, name) = match names.get(&address) {
+            Some(name) => ("function", name.as_str()),
+            None => ("lambda", function.name.as_str()),
+        };
+        let arity = function.arity;
+        writeln!(self.output, "\n.{directive} #{id} {name:?} {arity}").unwrap();
+        self.chunk(&function.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        let instructions = chunk.instructions().collect::<Vec<_>>();
+        let mut labels = instructions
+            .iter()
+            .filter_map(|(offset, instruction)| {
+                let relative = instruction.offset()?;
+                Some((*offset as isize + relative) as usize)
+            })
+            .collect::<BTreeSet<_>>();
+        for handler in chunk.handlers() {
+            labels.extend([handler.start, handler.end, handler.target]);
+        }
+        let labels = labels
+            .into_iter()
+            .enumerate()
+            .map(|(label, index)| (index, format!("L{label}")))
+            .collect::<HashMap<_, _>>();
+
+        let mut line = None;
+        for (offset, instruction) in &instructions {
+            if let Some(label) = labels.get(offset) {
+                writeln!(self.output, "{label}:").unwrap();
+            }
+            self.annotate(chunk.span(*offset), &mut line);
+            write!(self.output, "    {}", mnemonic(instruction)).unwrap();
+            match instruction {
+                Instruction::LoadConstant(constant) | Instruction::GetProperty(constant) => {
+                    write!(self.output, " ").unwrap();
+                    self.constant(chunk.constant(*constant));
+                }
+                Instruction::DefineGlobal(slot)
+                | Instruction::GetGlobal(slot)
+                | Instruction::SetGlobal(slot)
+                | Instruction::InvokeGlobal(slot) => {
+                    write!(self.output, " {:?}", self.globals[*slot]).unwrap();
+                }
+                Instruction::InvokeNative(index) => {
+                    write!(self.output, " {:?}", NATIVE_FUNCTIONS[*index].0).unwrap();
+                }
+                Instruction::GetLocal(operand)
+                | Instruction::SetLocal(operand)
+                | Instruction::Import(operand) => write!(self.output, " {operand}").unwrap(),
+                Instruction::JumpIfFalse(relative)
+                | Instruction::PopJumpIfFalse(relative)
+                | Instruction::Jump(relative) => {
+                    let label = &labels[&((*offset as isize + relative) as usize)];
+                    write!(self.output, " {label}").unwrap();
+                }
+                _ => {}
+            }
+            writeln!(self.output).unwrap();
+        }
+        if let Some(label) = labels.get(&chunk.len()) {
+            writeln!(self.output, "{label}:").unwrap();
+        }
+        for handler in chunk.handlers() {
+            let [start, end, target] = [handler.start, handler.end, handler.target];
+            let (start, end, target) = (&labels[&start], &labels[&end], &labels[&target]);
+            let depth = handler.depth;
+            writeln!(self.output, "    .handler {start} {end} {target} {depth}").unwrap();
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Number(number) => write!(self.output, "{number}"),
+            Constant::String(string) => write!(self.output, "{string:?}"),
+            Constant::Function(function) => {
+                write!(self.output, "#{}", self.functions[&Rc::as_ptr(function)])
+            }
+        }
+        .unwrap();
+    }
+
+    // Source lines are written as comments before the first instruction compiled from them.
+    // Spans out of the source map (e.g. those of assembled bytecode) are skipped.
+    fn annotate(&mut self, span: &Span, last: &mut Option<(FileId, usize)>) {
+        let files = self.sources.files();
+        if *span == Span::default() {
+            return;
+        }
+        let Ok(line) = files.line_index(span.file, span.range.start) else {
+            return;
+        };
+        if *last == Some((span.file, line)) {
+            return;
+        }
+        *last = Some((span.file, line));
+        let range = files.line_range(span.file, line).unwrap();
+        let text = self.sources.source(span.file)[range].trim();
+        writeln!(self.output, "    ; {}: {text}", line + 1).unwrap();
+    }
+}
+
+// A chunk being assembled, whose labels are resolved when it ends.
+struct PendingChunk {
+    owner: Owner,
+    builder: ChunkBuilder,
+    labels: HashMap<String, usize>,
+    // jumps by their indices, with the labels they refer to.
+    jumps: Vec<(usize, String, usize)>,
+    handlers: Vec<([String; 3], usize, usize)>,
+}
+
+enum Owner {
+    Function {
+        id: usize,
+        name: String,
+        arity: usize,
+        span: Span,
+        // whether the function is defined in the module, rather than a lambda.
+        named: bool,
+    },
+    Script,
+}
+
+struct Assembler {
+    modules: Vec<PendingModule>,
+    functions: HashMap<usize, Rc<Function>>,
+    chunk: Option<PendingChunk>,
+    // imports with their lines, which are checked once all modules are known.
+    imports: Vec<(usize, usize)>,
+}
+
+struct PendingModule {
+    name: String,
+    line: usize,
+    functions: HashMap<String, Rc<Function>>,
+    globals: Vec<String>,
+    script: Option<Rc<Chunk>>,
+}
+
+impl Assembler {
+    fn line(&mut self, number: usize, text: &str, span: Span) -> Result<(), AssemblyError> {
+        let mut tokens = tokenize(text).map_err(|message| AssemblyError {
+            line: number,
+            message,
+        })?;
+        if let Some(label) = tokens.first().and_then(|token| token.strip_suffix(':')) {
+            let label = label.to_string();
+            tokens.remove(0);
+            let Some(chunk) = &mut self.chunk else {
+                invalid!(number, "label {label} is out of any chunk");
+            };
+            let index = chunk.builder.instructions.len();
+            if chunk.labels.insert(label.clone(), index).is_some() {
+                invalid!(number, "label {label} is already defined");
+            }
+        }
+        let Some((head, operands)) = tokens.split_first() else {
+            return Ok(());
+        };
+        match head.as_str() {
+            ".module" => {
+                self.end_chunk()?;
+                let [name] = operands else {
+                    invalid!(number, ".module expects a name");
+                };
+                self.modules.push(PendingModule {
+                    name: string(number, name)?,
+                    line: number,
+                    functions: HashMap::new(),
+                    globals: Vec::new(),
+                    script: None,
+                });
+            }
+            ".function" | ".lambda" => {
+                self.end_chunk()?;
+                if self.current_module(number)?.script.is_some() {
+                    invalid!(number, "functions are expected before .script");
+                }
+                let [id, name, arity] = operands else {
+                    invalid!(number, "{head} expects an id, a name and an arity");
+                };
+                let id = function_id(number, id)?;
+                if self.functions.contains_key(&id) {
+                    invalid!(number, "function #{id} is already defined");
+                }
+                self.begin_chunk(Owner::Function {
+                    id,
+                    name: string(number, name)?,
+                    arity: integer(number, arity)?,
+                    span,
+                    named: head == ".function",
+                });
+            }
+            ".script" => {
+                self.end_chunk()?;
+                if self.current_module(number)?.script.is_some() {
+                    invalid!(number, "the script of the module is already defined");
+                }
+                self.begin_chunk(Owner::Script);
+            }
+            ".handler" => {
+                let Some(chunk) = &mut self.chunk else {
+                    invalid!(number, ".handler is out of any chunk");
+                };
+                let [start, end, target, depth] = operands else {
+                    invalid!(number, ".handler expects 3 labels and a depth");
+                };
+                let labels = [start.clone(), end.clone(), target.clone()];
+                chunk
+                    .handlers
+                    .push((labels, integer(number, depth)?, number));
+            }
+            directive if directive.starts_with('.') => {
+                invalid!(number, "unknown directive {directive}")
+            }
+            mnemonic => self.instruction(number, mnemonic, operands, span)?,
+        }
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        number: usize,
+        mnemonic: &str,
+        operands: &[String],
+        span: Span,
+    ) -> Result<(), AssemblyError> {
+        let Some(chunk) = &mut self.chunk else {
+            invalid!(number, "instruction {mnemonic} is out of any chunk");
+        };
+        let index = chunk.builder.instructions.len();
+        let instruction = match (mnemonic, operands) {
+            ("LoadConstant" | "GetProperty", [operand]) => {
+                let constant = match operand.as_bytes()[0] {
+                    b'"' => Constant::String(string(number, operand)?),
+                    b'#' => {
+                        let id = function_id(number, operand)?;
+                        match self.functions.get(&id) {
+                            Some(function) => Constant::Function(function.clone()),
+                            None => invalid!(number, "function #{id} is not defined yet"),
+                        }
+                    }
+                    _ => match operand.parse() {
+                        Ok(number) => Constant::Number(number),
+                        Err(_) => invalid!(number, "invalid constant {operand}"),
+                    },
+                };
+                let constant = chunk.builder.define(constant);
+                match mnemonic {
+                    "LoadConstant" => Instruction::LoadConstant(constant),
+                    _ => Instruction::GetProperty(constant),
+                }
+            }
+            ("DefineGlobal" | "GetGlobal" | "SetGlobal" | "InvokeGlobal", [name]) => {
+                let name = string(number, name)?;
+                // chunks are always in a module.
+                let slot = global(&mut self.modules.last_mut().unwrap().globals, name);
+                match mnemonic {
+                    "DefineGlobal" => Instruction::DefineGlobal(slot),
+                    "GetGlobal" => Instruction::GetGlobal(slot),
+                    "SetGlobal" => Instruction::SetGlobal(slot),
+                    _ => Instruction::InvokeGlobal(slot),
+                }
+            }
+            ("InvokeNative", [name]) => {
+                let name = string(number, name)?;
+                match NATIVE_FUNCTIONS
+                    .iter()
+                    .position(|(native, _)| *native == name)
+                {
+                    Some(index) => Instruction::InvokeNative(index),
+                    None => invalid!(number, "native function {name:?} is not defined"),
+                }
+            }
+            ("GetLocal", [slot]) => Instruction::GetLocal(integer(number, slot)?),
+            ("SetLocal", [slot]) => Instruction::SetLocal(integer(number, slot)?),
+            ("Import", [module]) => {
+                let module = integer(number, module)?;
+                self.imports.push((module, number));
+                Instruction::Import(module)
+            }
+            ("JumpIfFalse" | "PopJumpIfFalse" | "Jump", [label]) => {
+                chunk.jumps.push((index, label.clone(), number));
+                match mnemonic {
+                    "JumpIfFalse" => Instruction::JumpIfFalse(0),
+                    "PopJumpIfFalse" => Instruction::PopJumpIfFalse(0),
+                    _ => Instruction::Jump(0),
+                }
+            }
+            (_, []) => match NULLARY.iter().find(|(name, _)| *name == mnemonic) {
+                Some((_, instruction)) => instruction.clone(),
+                None => invalid!(number, "unknown instruction {mnemonic}"),
+            },
+            _ => invalid!(number, "unexpected operands of {mnemonic}"),
+        };
+        if let Some(index) = instruction.index().filter(|index| *index > LONG_OPERAND) {
+            invalid!(
+                number,
+                "operand {index} of {mnemonic} is over {LONG_OPERAND}"
+            );
+        }
+        chunk.builder.write(instruction, span);
+        Ok(())
+    }
+
+    fn current_module(&mut self, number: usize) -> Result<&mut PendingModule, AssemblyError> {
+        match self.modules.last_mut() {
+            Some(module) => Ok(module),
+            None => invalid!(number, "expected .module first"),
+        }
+    }
+
+    fn begin_chunk(&mut self, owner: Owner) {
+        self.chunk = Some(PendingChunk {
+            owner,
+            builder: ChunkBuilder::new(),
+            labels: HashMap::new(),
+            jumps: Vec::new(),
+            handlers: Vec::new(),
+        });
+    }
+
+    fn end_chunk(&mut self) -> Result<(), AssemblyError> {
+        let Some(mut chunk) = self.chunk.take() else {
+            return Ok(());
+        };
+        let resolve = |label: &String, number: usize| match chunk.labels.get(label) {
+            Some(index) => Ok(*index),
+            None => invalid!(number, "label {label} is not defined"),
+        };
+        for (index, label, number) in &chunk.jumps {
+            let target = resolve(label, *number)?;
+            *chunk.builder.instructions[*index].offset_mut().unwrap() =
+                target as isize - *index as isize;
+        }
+        let length = chunk.builder.instructions.len();
+        for ([start, end, target], depth, number) in &chunk.handlers {
+            let handler = Handler {
+                start: resolve(start, *number)?,
+                end: resolve(end, *number)?,
+                target: resolve(target, *number)?,
+                depth: *depth,
+            };
+            if handler.start > handler.end || handler.target >= length {
+                invalid!(*number, "handler {handler:?} is out of the chunk");
+            }
+            chunk.builder.handlers.push(handler);
+        }
+
+        let module = self.modules.len() - 1;
+        let built = Rc::new(chunk.builder.build());
+        match chunk.owner {
+            Owner::Function {
+                id,
+                name,
+                arity,
+                span,
+                named,
+            } => {
+                let function = Rc::new(Function {
+                    name: name.clone(),
+                    span,
+                    chunk: built,
+                    arity,
+                    module,
+                });
+                // named functions are globals as well, defined from the start.
+                if named {
+                    global(&mut self.modules[module].globals, name.clone());
+                    self.modules[module]
+                        .functions
+                        .insert(name, function.clone());
+                }
+                self.functions.insert(id, function);
+            }
+            Owner::Script => self.modules[module].script = Some(built),
+        }
+        Ok(())
+    }
+}
+
+// Split a line into tokens, where strings are kept quoted and comments are dropped.
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut characters = text.char_indices().peekable();
+    while let Some(&(start, character)) = characters.peek() {
+        match character {
+            ';' => break,
+            _ if character.is_whitespace() => {
+                characters.next();
+            }
+            '"' => {
+                characters.next();
+                let mut escaped = false;
+                let end = loop {
+                    match characters.next() {
+                        Some((index, '"')) if !escaped => break index + 1,
+                        Some((_, '\\')) => escaped = !escaped,
+                        Some(_) => escaped = false,
+                        None => return Err(String::from("unterminated string")),
+                    }
+                };
+                tokens.push(text[start..end].to_string());
+            }
+            _ => {
+                let mut end = text.len();
+                while let Some(&(index, character)) = characters.peek() {
+                    if character.is_whitespace() || character == ';' {
+                        end = index;
+                        break;
+                    }
+                    characters.next();
+                }
+                tokens.push(text[start..end].to_string());
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Strings are quoted with the escapes of Rust, in which they are written by `{:?}`.
+fn string(number: usize, token: &str) -> Result<String, AssemblyError> {
+    let Some(content) = token
+        .strip_prefix('"')
+        .and_then(|token| token.strip_suffix('"'))
+    else {
+        invalid!(number, "expected a string, found {token}");
+    };
+    let mut string = String::new();
+    let mut characters = content.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            string.push(character);
+            continue;
+        }
+        let escaped = match characters.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(character @ ('\\' | '"' | '\'')) => character,
+            Some('u') => {
+                let rest = characters.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .and_then(|(code, _)| u32::from_str_radix(code, 16).ok())
+                    .and_then(char::from_u32);
+                let Some(code) = code else {
+                    invalid!(number, "invalid unicode escape in {token}");
+                };
+                let length = rest.find('}').unwrap() + 1;
+                characters = rest[length..].chars();
+                code
+            }
+            _ => invalid!(number, "invalid escape in {token}"),
+        };
+        string.push(escaped);
+    }
+    Ok(string)
+}
+
+fn integer(number: usize, token: &str) -> Result<usize, AssemblyError> {
+    match token.parse() {
+        Ok(integer) => Ok(integer),
+        Err(_) => invalid!(number, "expected an integer, found {token}"),
+    }
+}
+
+fn function_id(number: usize, token: &str) -> Result<usize, AssemblyError> {
+    match token.strip_prefix('#').map(str::parse) {
+        Some(Ok(id)) => Ok(id),
+        _ => invalid!(number, "expected a function id, found {token}"),
+    }
+}
+
+impl Bytecode {
+    // Disassemble modules into assembly, annotated with source lines in `sources`.
+    pub fn disassemble(&self, sources: &SourceMap) -> String {
+        let mut disassembler = Disassembler {
+            sources,
+            output: String::new(),
+            globals: Vec::new(),
+            functions: HashMap::new(),
+        };
+        for (index, module) in self.modules.iter().enumerate() {
+            if index > 0 {
+                writeln!(disassembler.output).unwrap();
+            }
+            disassembler.module(module);
+        }
+        disassembler.output
+    }
+
+    // Assemble modules from assembly, which is the source of `file`. Instructions are spanned
+    // by the lines they're written in, which runtime errors are reported against.
+    pub fn assemble(source: &str, file: FileId) -> Result<Bytecode, AssemblyError> {
+        let mut assembler = Assembler {
+            modules: Vec::new(),
+            functions: HashMap::new(),
+            chunk: None,
+            imports: Vec::new(),
+        };
+        let mut offset = 0;
+        for (index, line) in source.split('\n').enumerate() {
+            let text = line.strip_suffix('\r').unwrap_or(line);
+            let start = offset + text.len() - text.trim_start().len();
+            let span = Span::new(file, start..offset + text.trim_end().len());
+            offset += line.len() + 1;
+            assembler.line(index + 1, text, span)?;
+        }
+        assembler.end_chunk()?;
+
+        if assembler.modules.is_empty() {
+            invalid!(1, "there's no main module");
+        }
+        for (module, number) in &assembler.imports {
+            if *module >= assembler.modules.len() {
+                invalid!(*number, "module {module} is not defined");
+            }
+        }
+        let mut modules = Vec::new();
+        for module in assembler.modules {
+            let Some(script) = module.script else {
+                invalid!(module.line, "module {:?} has no .script", module.name);
+            };
+            modules.push(Module {
+                name: module.name,
+                file,
+                functions: module.functions,
+                globals: module.globals,
+                script,
+            });
+        }
+        Ok(Bytecode { modules })
+    }
+}
+
+// Slot of the global among those of a module, which is allocated at the first time.
+fn global(globals: &mut Vec<String>, name: String) -> usize {
+    match globals.iter().position(|global| *global == name) {
+        Some(slot) => slot,
+        None => {
+            globals.push(name);
+            globals.len() - 1
+        }
+    }
+}
@@ -1,22 +1,26 @@
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::mem;
-use std::ops::Deref;
+use std::rc::Rc;
 
-use crate::{Instruction, Span};
+use crate::{Function, Instruction, Span};
 use crate::bytecode::backpatcher::{Backpatch, JumpBackpatcher, JumpIfFalseBackpatcher};
+use crate::bytecode::encoding;
 
 #[derive(Debug, Clone)]
 pub enum Constant {
     Number(f64),
     String(String),
+    Function(Rc<Function>),
 }
 
 impl PartialEq for Constant {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Constant::Number(this), Constant::Number(that)) => (this - that).abs() < f64::EPSILON,
+            // constants are distinguished by bits, e.g. `-0` and `0`, unlike values.
+            (Constant::Number(this), Constant::Number(that)) => this.to_bits() == that.to_bits(),
             (Constant::String(this), Constant::String(that)) => this == that,
+            (Constant::Function(this), Constant::Function(that)) => Rc::ptr_eq(this, that),
             _ => false,
         }
     }
@@ -30,14 +34,27 @@ impl Hash for Constant {
         match self {
             Constant::Number(number) => number.to_bits().hash(state),
             Constant::String(string) => string.hash(state),
+            Constant::Function(function) => Rc::as_ptr(function).hash(state),
         }
     }
 }
 
+// Exceptions thrown by instructions in `start..end` are caught by the instruction at
+// `target`, with the stack of the current frame truncated to `depth` values (locals
+// visible to the try statement).
+#[derive(Debug, Clone)]
+pub struct Handler {
+    pub start: usize,
+    pub end: usize,
+    pub target: usize,
+    pub depth: usize,
+}
+
 pub struct ChunkBuilder {
     pub instructions: Vec<Instruction>,
     pub spans: Vec<Span>,
     pub constants: Vec<Constant>,
+    pub handlers: Vec<Handler>,
     constants_cache: HashMap<Constant, usize>,
 }
 
@@ -47,16 +64,13 @@ impl ChunkBuilder {
             instructions: Vec::new(),
             spans: Vec::new(),
             constants: Vec::new(),
+            handlers: Vec::new(),
             constants_cache: HashMap::new(),
         }
     }
 
     pub fn build(self) -> Chunk {
-        Chunk {
-            instructions: self.instructions,
-            spans: self.spans,
-            constants: self.constants,
-        }
+        encoding::encode(self)
     }
 
     pub fn write(&mut self, instruction: Instruction, span: Span) {
@@ -65,8 +79,13 @@ impl ChunkBuilder {
     }
 
     pub fn append_backpatch(&mut self, instruction: Instruction) -> Box<dyn Backpatch> {
+        let span = self.spans.last().unwrap().clone();
+        self.write_backpatch(instruction, span)
+    }
+
+    pub fn write_backpatch(&mut self, instruction: Instruction, span: Span) -> Box<dyn Backpatch> {
         let index = self.instructions.len();
-        self.append(instruction.clone());
+        self.write(instruction.clone(), span);
         match instruction {
             Instruction::JumpIfFalse(_) => {
                 Box::new(JumpIfFalseBackpatcher::new(&mut self.instructions, index))
@@ -92,22 +111,50 @@ impl ChunkBuilder {
     }
 }
 
+impl Default for ChunkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Instructions encoded into a stream of bytes, where jumps and handlers are in offsets of
+// the stream. Spans are kept in runs, each from the offset it starts at.
 pub struct Chunk {
-    instructions: Vec<Instruction>,
-    spans: Vec<Span>,
+    code: Vec<u8>,
+    spans: Vec<(usize, Span)>,
     constants: Vec<Constant>,
+    handlers: Vec<Handler>,
 }
 
 impl Chunk {
+    pub(crate) fn from_parts(
+        code: Vec<u8>,
+        spans: Vec<(usize, Span)>,
+        constants: Vec<Constant>,
+        handlers: Vec<Handler>,
+    ) -> Self {
+        Self {
+            code,
+            spans,
+            constants,
+            handlers,
+        }
+    }
+
+    // length of the code in bytes.
     pub fn len(&self) -> usize {
-        self.instructions.len()
+        self.code.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.instructions.is_empty()
+        self.code.is_empty()
     }
 
-    pub fn spans(&self) -> &Vec<Span> {
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn spans(&self) -> &Vec<(usize, Span)> {
         &self.spans
     }
 
@@ -115,19 +162,24 @@ impl Chunk {
         &self.constants
     }
 
-    pub fn span(&self, index: usize) -> &Span {
-        &self.spans[index]
+    pub fn handlers(&self) -> &Vec<Handler> {
+        &self.handlers
     }
 
-    pub fn constant(&self, index: usize) -> &Constant {
-        &self.constants[index]
+    // inner handlers are always defined earlier, so the first one covering the offset wins.
+    pub fn handler(&self, offset: usize) -> Option<&Handler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.start <= offset && offset < handler.end)
     }
-}
 
-impl Deref for Chunk {
-    type Target = [Instruction];
+    // span of the instruction at `offset`, in the last run starting before it.
+    pub fn span(&self, offset: usize) -> &Span {
+        let run = self.spans.partition_point(|(start, _)| *start <= offset);
+        &self.spans[run - 1].1
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.instructions
+    pub fn constant(&self, index: usize) -> &Constant {
+        &self.constants[index]
     }
 }
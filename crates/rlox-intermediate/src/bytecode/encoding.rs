@@ -0,0 +1,367 @@
+use crate::{Chunk, ChunkBuilder, Handler, Instruction, LONG_OPERAND};
+
+// Opcodes of the code stream in chunks, each followed by its operand if any. Operands are
+// little endian, of a byte for indices and 2 bytes for jump offsets, or 3 and 4 bytes in
+// long variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    LoadConstant,
+    LoadConstantLong,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Negate,
+    Not,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    True,
+    False,
+    Nil,
+    Print,
+    Pop,
+    Duplicate,
+    DefineGlobal,
+    DefineGlobalLong,
+    GetGlobal,
+    GetGlobalLong,
+    SetGlobal,
+    SetGlobalLong,
+    GetLocal,
+    GetLocalLong,
+    SetLocal,
+    SetLocalLong,
+    JumpIfFalse,
+    JumpIfFalseLong,
+    PopJumpIfFalse,
+    PopJumpIfFalseLong,
+    Jump,
+    JumpLong,
+    PrepareInvoke,
+    Invoke,
+    InvokeGlobal,
+    InvokeGlobalLong,
+    InvokeNative,
+    Return,
+    Throw,
+    Import,
+    ImportLong,
+    GetProperty,
+    GetPropertyLong,
+}
+
+// Opcodes by their bytes, in the order of variants.
+const OPCODES: &[Opcode] = &[
+    Opcode::LoadConstant,
+    Opcode::LoadConstantLong,
+    Opcode::Add,
+    Opcode::Subtract,
+    Opcode::Multiply,
+    Opcode::Divide,
+    Opcode::Modulo,
+    Opcode::Negate,
+    Opcode::Not,
+    Opcode::Greater,
+    Opcode::Less,
+    Opcode::GreaterEqual,
+    Opcode::LessEqual,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Nil,
+    Opcode::Print,
+    Opcode::Pop,
+    Opcode::Duplicate,
+    Opcode::DefineGlobal,
+    Opcode::DefineGlobalLong,
+    Opcode::GetGlobal,
+    Opcode::GetGlobalLong,
+    Opcode::SetGlobal,
+    Opcode::SetGlobalLong,
+    Opcode::GetLocal,
+    Opcode::GetLocalLong,
+    Opcode::SetLocal,
+    Opcode::SetLocalLong,
+    Opcode::JumpIfFalse,
+    Opcode::JumpIfFalseLong,
+    Opcode::PopJumpIfFalse,
+    Opcode::PopJumpIfFalseLong,
+    Opcode::Jump,
+    Opcode::JumpLong,
+    Opcode::PrepareInvoke,
+    Opcode::Invoke,
+    Opcode::InvokeGlobal,
+    Opcode::InvokeGlobalLong,
+    Opcode::InvokeNative,
+    Opcode::Return,
+    Opcode::Throw,
+    Opcode::Import,
+    Opcode::ImportLong,
+    Opcode::GetProperty,
+    Opcode::GetPropertyLong,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    // Bytes of the operand following the opcode.
+    pub fn width(self) -> usize {
+        match self {
+            Opcode::LoadConstant
+            | Opcode::DefineGlobal
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::InvokeGlobal
+            | Opcode::InvokeNative
+            | Opcode::Import
+            | Opcode::GetProperty => 1,
+            Opcode::JumpIfFalse | Opcode::PopJumpIfFalse | Opcode::Jump => 2,
+            Opcode::LoadConstantLong
+            | Opcode::DefineGlobalLong
+            | Opcode::GetGlobalLong
+            | Opcode::SetGlobalLong
+            | Opcode::GetLocalLong
+            | Opcode::SetLocalLong
+            | Opcode::InvokeGlobalLong
+            | Opcode::ImportLong
+            | Opcode::GetPropertyLong => 3,
+            Opcode::JumpIfFalseLong | Opcode::PopJumpIfFalseLong | Opcode::JumpLong => 4,
+            _ => 0,
+        }
+    }
+}
+
+fn operand(bytes: &[u8]) -> isize {
+    match *bytes {
+        [] => 0,
+        [byte] => byte as isize,
+        [low, high] => i16::from_le_bytes([low, high]) as isize,
+        [low, middle, high] => u32::from_le_bytes([low, middle, high, 0]) as isize,
+        [a, b, c, d] => i32::from_le_bytes([a, b, c, d]) as isize,
+        _ => unreachable!(),
+    }
+}
+
+// Opcode of an instruction in the short or long variant, and its operand apart from jumps.
+fn opcode(instruction: &Instruction, long: bool) -> (Opcode, usize) {
+    let variant = |short, long_variant| if long { long_variant } else { short };
+    match *instruction {
+        Instruction::LoadConstant(index) => {
+            let opcode = variant(Opcode::LoadConstant, Opcode::LoadConstantLong);
+            (opcode, index)
+        }
+        Instruction::Add => (Opcode::Add, 0),
+        Instruction::Subtract => (Opcode::Subtract, 0),
+        Instruction::Multiply => (Opcode::Multiply, 0),
+        Instruction::Divide => (Opcode::Divide, 0),
+        Instruction::Modulo => (Opcode::Modulo, 0),
+        Instruction::Negate => (Opcode::Negate, 0),
+        Instruction::Not => (Opcode::Not, 0),
+        Instruction::Greater => (Opcode::Greater, 0),
+        Instruction::Less => (Opcode::Less, 0),
+        Instruction::GreaterEqual => (Opcode::GreaterEqual, 0),
+        Instruction::LessEqual => (Opcode::LessEqual, 0),
+        Instruction::Equal => (Opcode::Equal, 0),
+        Instruction::NotEqual => (Opcode::NotEqual, 0),
+        Instruction::True => (Opcode::True, 0),
+        Instruction::False => (Opcode::False, 0),
+        Instruction::Nil => (Opcode::Nil, 0),
+        Instruction::Print => (Opcode::Print, 0),
+        Instruction::Pop => (Opcode::Pop, 0),
+        Instruction::Duplicate => (Opcode::Duplicate, 0),
+        Instruction::DefineGlobal(slot) => {
+            let opcode = variant(Opcode::DefineGlobal, Opcode::DefineGlobalLong);
+            (opcode, slot)
+        }
+        Instruction::GetGlobal(slot) => (variant(Opcode::GetGlobal, Opcode::GetGlobalLong), slot),
+        Instruction::SetGlobal(slot) => (variant(Opcode::SetGlobal, Opcode::SetGlobalLong), slot),
+        Instruction::GetLocal(slot) => (variant(Opcode::GetLocal, Opcode::GetLocalLong), slot),
+        Instruction::SetLocal(slot) => (variant(Opcode::SetLocal, Opcode::SetLocalLong), slot),
+        Instruction::JumpIfFalse(_) => {
+            let opcode = variant(Opcode::JumpIfFalse, Opcode::JumpIfFalseLong);
+            (opcode, 0)
+        }
+        Instruction::PopJumpIfFalse(_) => {
+            let opcode = variant(Opcode::PopJumpIfFalse, Opcode::PopJumpIfFalseLong);
+            (opcode, 0)
+        }
+        Instruction::Jump(_) => (variant(Opcode::Jump, Opcode::JumpLong), 0),
+        Instruction::PrepareInvoke => (Opcode::PrepareInvoke, 0),
+        Instruction::Invoke => (Opcode::Invoke, 0),
+        Instruction::InvokeGlobal(slot) => {
+            let opcode = variant(Opcode::InvokeGlobal, Opcode::InvokeGlobalLong);
+            (opcode, slot)
+        }
+        // there are never more native functions than a short operand holds.
+        Instruction::InvokeNative(index) => (Opcode::InvokeNative, index),
+        Instruction::Return => (Opcode::Return, 0),
+        Instruction::Throw => (Opcode::Throw, 0),
+        Instruction::Import(module) => (variant(Opcode::Import, Opcode::ImportLong), module),
+        Instruction::GetProperty(index) => {
+            let opcode = variant(Opcode::GetProperty, Opcode::GetPropertyLong);
+            (opcode, index)
+        }
+    }
+}
+
+// Instruction of an opcode and its operand, where jump offsets are in bytes.
+fn instruction(opcode: Opcode, operand: isize) -> Instruction {
+    let index = operand as usize;
+    match opcode {
+        Opcode::LoadConstant | Opcode::LoadConstantLong => Instruction::LoadConstant(index),
+        Opcode::Add => Instruction::Add,
+        Opcode::Subtract => Instruction::Subtract,
+        Opcode::Multiply => Instruction::Multiply,
+        Opcode::Divide => Instruction::Divide,
+        Opcode::Modulo => Instruction::Modulo,
+        Opcode::Negate => Instruction::Negate,
+        Opcode::Not => Instruction::Not,
+        Opcode::Greater => Instruction::Greater,
+        Opcode::Less => Instruction::Less,
+        Opcode::GreaterEqual => Instruction::GreaterEqual,
+        Opcode::LessEqual => Instruction::LessEqual,
+        Opcode::Equal => Instruction::Equal,
+        Opcode::NotEqual => Instruction::NotEqual,
+        Opcode::True => Instruction::True,
+        Opcode::False => Instruction::False,
+        Opcode::Nil => Instruction::Nil,
+        Opcode::Print => Instruction::Print,
+        Opcode::Pop => Instruction::Pop,
+        Opcode::Duplicate => Instruction::Duplicate,
+        Opcode::DefineGlobal | Opcode::DefineGlobalLong => Instruction::DefineGlobal(index),
+        Opcode::GetGlobal | Opcode::GetGlobalLong => Instruction::GetGlobal(index),
+        Opcode::SetGlobal | Opcode::SetGlobalLong => Instruction::SetGlobal(index),
+        Opcode::GetLocal | Opcode::GetLocalLong => Instruction::GetLocal(index),
+        Opcode::SetLocal | Opcode::SetLocalLong => Instruction::SetLocal(index),
+        Opcode::JumpIfFalse | Opcode::JumpIfFalseLong => Instruction::JumpIfFalse(operand),
+        Opcode::PopJumpIfFalse | Opcode::PopJumpIfFalseLong => Instruction::PopJumpIfFalse(operand),
+        Opcode::Jump | Opcode::JumpLong => Instruction::Jump(operand),
+        Opcode::PrepareInvoke => Instruction::PrepareInvoke,
+        Opcode::Invoke => Instruction::Invoke,
+        Opcode::InvokeGlobal | Opcode::InvokeGlobalLong => Instruction::InvokeGlobal(index),
+        Opcode::InvokeNative => Instruction::InvokeNative(index),
+        Opcode::Return => Instruction::Return,
+        Opcode::Throw => Instruction::Throw,
+        Opcode::Import | Opcode::ImportLong => Instruction::Import(index),
+        Opcode::GetProperty | Opcode::GetPropertyLong => Instruction::GetProperty(index),
+    }
+}
+
+// Encode instructions into the code stream, with jump offsets, handlers and spans turned
+// from instructions into bytes.
+pub(crate) fn encode(builder: ChunkBuilder) -> Chunk {
+    let instructions = &builder.instructions;
+    let mut long = instructions
+        .iter()
+        .map(|instruction| opcode(instruction, false).1 > u8::MAX as usize)
+        .collect::<Vec<_>>();
+    // jumps are widened until their offsets fit, which may lengthen other jumps over them.
+    let offsets = loop {
+        let mut offsets = Vec::with_capacity(instructions.len() + 1);
+        let mut offset = 0;
+        for (index, instruction) in instructions.iter().enumerate() {
+            offsets.push(offset);
+            offset += 1 + opcode(instruction, long[index]).0.width();
+        }
+        offsets.push(offset);
+
+        let mut widened = false;
+        for (index, instruction) in instructions.iter().enumerate() {
+            if let (Some(relative), false) = (instruction.offset(), long[index]) {
+                let target = (index as isize + relative) as usize;
+                let bytes = offsets[target] as isize - offsets[index] as isize;
+                if i16::try_from(bytes).is_err() {
+                    long[index] = true;
+                    widened = true;
+                }
+            }
+        }
+        if !widened {
+            break offsets;
+        }
+    };
+
+    let mut code = Vec::with_capacity(offsets[instructions.len()]);
+    for (index, instruction) in instructions.iter().enumerate() {
+        let (opcode, operand) = opcode(instruction, long[index]);
+        code.push(opcode as u8);
+        let operand = match instruction.offset() {
+            Some(relative) => {
+                let target = (index as isize + relative) as usize;
+                offsets[target] as isize - offsets[index] as isize
+            }
+            None => {
+                assert!(operand <= LONG_OPERAND, "operand {operand} is too large");
+                operand as isize
+            }
+        };
+        code.extend(&operand.to_le_bytes()[..opcode.width()]);
+    }
+
+    // consecutive instructions of the same span share an entry.
+    let mut spans = Vec::new();
+    for (index, span) in builder.spans.into_iter().enumerate() {
+        if spans.last().is_none_or(|(_, last)| *last != span) {
+            spans.push((offsets[index], span));
+        }
+    }
+    let handlers = builder
+        .handlers
+        .iter()
+        .map(|handler| Handler {
+            start: offsets[handler.start],
+            end: offsets[handler.end],
+            target: offsets[handler.target],
+            depth: handler.depth,
+        })
+        .collect();
+    Chunk::from_parts(code, spans, builder.constants, handlers)
+}
+
+impl Chunk {
+    // Opcode at `offset` with its operand, and the offset of the next one. The code is
+    // expected to be well-formed, i.e. built or verified.
+    pub fn fetch(&self, offset: usize) -> (Opcode, isize, usize) {
+        let code = self.code();
+        let opcode = OPCODES[code[offset] as usize];
+        let next = offset + 1 + opcode.width();
+        (opcode, operand(&code[offset + 1..next]), next)
+    }
+
+    // Same as `fetch`, but `None` for unknown opcodes and truncated operands.
+    pub fn try_fetch(&self, offset: usize) -> Option<(Opcode, isize, usize)> {
+        let code = self.code();
+        let opcode = Opcode::from_byte(*code.get(offset)?)?;
+        let next = offset + 1 + opcode.width();
+        Some((opcode, operand(code.get(offset + 1..next)?), next))
+    }
+
+    // Instruction at `offset` and the offset of the next one, where jump offsets are in bytes.
+    pub fn instruction(&self, offset: usize) -> (Instruction, usize) {
+        let (opcode, operand, next) = self.fetch(offset);
+        (instruction(opcode, operand), next)
+    }
+
+    // Instructions along with their offsets.
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, Instruction)> + '_ {
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            if offset == self.len() {
+                return None;
+            }
+            let (instruction, next) = self.instruction(offset);
+            Some((std::mem::replace(&mut offset, next), instruction))
+        })
+    }
+}
@@ -7,13 +7,17 @@ pub enum Instruction {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
     Negate,
     Not,
 
     /* Relational */
     Greater,
     Less,
+    GreaterEqual,
+    LessEqual,
     Equal,
+    NotEqual,
 
     /* Special literals */
     True,
@@ -23,19 +27,71 @@ pub enum Instruction {
     /* Stack operation */
     Print,
     Pop,
+    Duplicate,
 
     /* Variable operation */
-    DefineGlobal,
-    GetGlobal,
-    SetGlobal,
+    // globals are in slots of the module, allocated to their names at compile time.
+    DefineGlobal(usize),
+    GetGlobal(usize),
+    SetGlobal(usize),
     // there's no DefineLocal because locals are "defined" when initializer expression evaluated.
     GetLocal(usize),
     SetLocal(usize),
 
     /* Control flow */
     JumpIfFalse(isize),
+    // pops the condition, unlike `JumpIfFalse`.
+    PopJumpIfFalse(isize),
     Jump(isize),
     PrepareInvoke,
+    // invokes a function value on the stack above the arguments.
     Invoke,
+    // invokes the function value in the global slot.
+    InvokeGlobal(usize),
+    // invokes the native function at the index of `NATIVE_FUNCTIONS`.
+    InvokeNative(usize),
     Return,
+    Throw,
+
+    /* Module operation */
+    Import(usize),
+    GetProperty(usize),
+}
+
+impl Instruction {
+    // Offset of a jump, relative to the jump itself.
+    pub fn offset(&self) -> Option<isize> {
+        match self {
+            Instruction::JumpIfFalse(offset)
+            | Instruction::PopJumpIfFalse(offset)
+            | Instruction::Jump(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+
+    // Index of a constant, slot or module in the operand.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Instruction::LoadConstant(index)
+            | Instruction::DefineGlobal(index)
+            | Instruction::GetGlobal(index)
+            | Instruction::SetGlobal(index)
+            | Instruction::GetLocal(index)
+            | Instruction::SetLocal(index)
+            | Instruction::InvokeGlobal(index)
+            | Instruction::InvokeNative(index)
+            | Instruction::Import(index)
+            | Instruction::GetProperty(index) => Some(*index),
+            _ => None,
+        }
+    }
+
+    pub fn offset_mut(&mut self) -> Option<&mut isize> {
+        match self {
+            Instruction::JumpIfFalse(offset)
+            | Instruction::PopJumpIfFalse(offset)
+            | Instruction::Jump(offset) => Some(offset),
+            _ => None,
+        }
+    }
 }
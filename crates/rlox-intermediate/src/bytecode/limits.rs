@@ -0,0 +1,7 @@
+// Limits shared by the compiler, the verifier and the VM.
+
+// Largest index in operands of long variants.
+pub const LONG_OPERAND: usize = (1 << 24) - 1;
+
+// Values on the stack of the VM, which no frame is allowed to exceed.
+pub const STACK_SIZE: usize = 1024;
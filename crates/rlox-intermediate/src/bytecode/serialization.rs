@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use crate::{Bytecode, Chunk, Constant, Function, Handler, Module};
+use crate::{SourceMap, Span, VerifyError};
+
+// Layout of a bytecode file, with integers encoded as LEB128 varints:
+//
+// - header: magic and version
+// - file table: name and source of every file, which spans point into
+// - number of modules
+// - function table: every function, including lambdas, before those referring to it
+// - module table: name, file, global names, script and named functions of every module
+//
+// Chunks are encoded as the constant pool, code stream, span runs and handlers.
+const MAGIC: &[u8; 4] = b"LOXC";
+const VERSION: u16 = 5;
+
+// Reasons why a bytecode file is rejected on load.
+#[derive(Debug)]
+pub enum DecodeError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    Malformed(String),
+    Unverifiable(VerifyError),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::NotBytecode => write!(f, "not a bytecode file"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {version}, expected {VERSION}")
+            }
+            DecodeError::Truncated => write!(f, "unexpected end of file"),
+            DecodeError::Malformed(reason) => write!(f, "malformed bytecode: {reason}"),
+            DecodeError::Unverifiable(error) => write!(f, "unverifiable bytecode: {error}"),
+        }
+    }
+}
+
+macro_rules! malformed {
+    ($($arguments: tt)*) => {
+        return Err(DecodeError::Malformed(format!($($arguments)*)))
+    };
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+    // indices of functions in the function table, keyed by their addresses.
+    functions: HashMap<*const Function, usize>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn usize(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend(value.as_bytes());
+    }
+
+    fn span(&mut self, span: &Span) {
+        self.usize(span.file);
+        self.usize(span.range.start);
+        self.usize(span.range.end);
+    }
+
+    // functions are written in post-order, since lambdas are constants of their enclosing ones.
+    fn collect_functions(&mut self, chunk: &Chunk, functions: &mut Vec<Rc<Function>>) {
+        for constant in chunk.constants() {
+            if let Constant::Function(function) = constant {
+                self.collect_function(function, functions);
+            }
+        }
+    }
+
+    fn collect_function(&mut self, function: &Rc<Function>, functions: &mut Vec<Rc<Function>>) {
+        if self.functions.contains_key(&Rc::as_ptr(function)) {
+            return;
+        }
+        self.collect_functions(&function.chunk, functions);
+        self.functions.insert(Rc::as_ptr(function), functions.len());
+        functions.push(function.clone());
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.string(&function.name);
+        self.span(&function.span);
+        self.usize(function.arity);
+        self.usize(function.module);
+        self.chunk(&function.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.usize(chunk.constants().len());
+        for constant in chunk.constants() {
+            match constant {
+                Constant::Number(number) => {
+                    self.u8(0);
+                    self.f64(*number);
+                }
+                Constant::String(string) => {
+                    self.u8(1);
+                    self.string(string);
+                }
+                Constant::Function(function) => {
+                    self.u8(2);
+                    self.usize(self.functions[&Rc::as_ptr(function)]);
+                }
+            }
+        }
+        self.usize(chunk.len());
+        self.bytes.extend(chunk.code());
+        self.usize(chunk.spans().len());
+        for (offset, span) in chunk.spans() {
+            self.usize(*offset);
+            self.span(span);
+        }
+        self.usize(chunk.handlers().len());
+        for handler in chunk.handlers() {
+            self.usize(handler.start);
+            self.usize(handler.end);
+            self.usize(handler.target);
+            self.usize(handler.depth);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    // lengths of sources in the file table, which spans are checked against.
+    files: Vec<usize>,
+    modules: usize,
+    functions: Vec<Rc<Function>>,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::Truncated)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&[u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .ok_or(DecodeError::Truncated)?;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(DecodeError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        let mut value = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        malformed!("varint is too long")
+    }
+
+    fn f64(&mut self) -> Result<f64, DecodeError> {
+        let bytes = self.bytes(8)?.try_into().unwrap();
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let length = self.usize()?;
+        match String::from_utf8(self.bytes(length)?.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => malformed!("string is not valid UTF-8"),
+        }
+    }
+
+    fn span(&mut self) -> Result<Span, DecodeError> {
+        let file = self.usize()?;
+        let (start, end) = (self.usize()?, self.usize()?);
+        match self.files.get(file) {
+            Some(length) if start <= end && end <= *length => Ok(Span::new(file, start..end)),
+            Some(_) => malformed!("span {start}..{end} is out of file {file}"),
+            None => malformed!("file {file} is not in the file table"),
+        }
+    }
+
+    fn module_index(&mut self) -> Result<usize, DecodeError> {
+        let module = self.usize()?;
+        if module >= self.modules {
+            malformed!("module {module} is not in the module table");
+        }
+        Ok(module)
+    }
+
+    // functions only refer to those before them in the function table.
+    fn function_index(&mut self) -> Result<Rc<Function>, DecodeError> {
+        let index = self.usize()?;
+        match self.functions.get(index) {
+            Some(function) => Ok(function.clone()),
+            None => malformed!("function {index} is not defined yet"),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, DecodeError> {
+        Ok(Function {
+            name: self.string()?,
+            span: self.span()?,
+            arity: self.usize()?,
+            module: self.module_index()?,
+            chunk: Rc::new(self.chunk()?),
+        })
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, DecodeError> {
+        let mut constants = Vec::new();
+        for _ in 0..self.usize()? {
+            let constant = match self.u8()? {
+                0 => Constant::Number(self.f64()?),
+                1 => Constant::String(self.string()?),
+                2 => Constant::Function(self.function_index()?),
+                tag => malformed!("unknown constant tag {tag}"),
+            };
+            constants.push(constant);
+        }
+        let length = self.usize()?;
+        let code = self.bytes(length)?.to_vec();
+        let mut spans = Vec::new();
+        for _ in 0..self.usize()? {
+            let offset = self.usize()?;
+            spans.push((offset, self.span()?));
+        }
+        let mut handlers = Vec::new();
+        for _ in 0..self.usize()? {
+            let handler = Handler {
+                start: self.usize()?,
+                end: self.usize()?,
+                target: self.usize()?,
+                depth: self.usize()?,
+            };
+            handlers.push(handler);
+        }
+        Ok(Chunk::from_parts(code, spans, constants, handlers))
+    }
+}
+
+impl Bytecode {
+    // Serialize modules along with their source files, which are required for reporting
+    // runtime errors.
+    pub fn serialize(&self, sources: &SourceMap) -> Vec<u8> {
+        let mut writer = Writer {
+            bytes: Vec::new(),
+            functions: HashMap::new(),
+        };
+        writer.bytes.extend(MAGIC);
+        writer.bytes.extend(VERSION.to_le_bytes());
+
+        writer.usize(sources.len());
+        for file in 0..sources.len() {
+            writer.string(sources.name(file));
+            writer.string(sources.source(file));
+        }
+
+        let mut functions = Vec::new();
+        for module in &self.modules {
+            for name in module.function_names() {
+                writer.collect_function(&module.functions[name], &mut functions);
+            }
+            writer.collect_functions(&module.script, &mut functions);
+        }
+        writer.usize(self.modules.len());
+        writer.usize(functions.len());
+        for function in &functions {
+            writer.function(function);
+        }
+        for module in &self.modules {
+            writer.string(&module.name);
+            writer.usize(module.file);
+            writer.usize(module.globals.len());
+            for global in &module.globals {
+                writer.string(global);
+            }
+            writer.chunk(&module.script);
+            let names = module.function_names();
+            writer.usize(names.len());
+            for name in names {
+                writer.string(name);
+                writer.usize(writer.functions[&Rc::as_ptr(&module.functions[name])]);
+            }
+        }
+        writer.bytes
+    }
+
+    // Deserialize modules and their source files, which are verified before being run.
+    pub fn deserialize(bytes: &[u8]) -> Result<(Bytecode, SourceMap), DecodeError> {
+        if !Self::is_serialized(bytes) {
+            return Err(DecodeError::NotBytecode);
+        }
+        let mut reader = Reader {
+            bytes,
+            position: MAGIC.len(),
+            files: Vec::new(),
+            modules: 0,
+            functions: Vec::new(),
+        };
+        let version = u16::from_le_bytes(reader.bytes(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let mut sources = SourceMap::new();
+        for _ in 0..reader.usize()? {
+            let name = reader.string()?;
+            let source = reader.string()?;
+            reader.files.push(source.len());
+            sources.add(name, source);
+        }
+
+        reader.modules = reader.usize()?;
+        if reader.modules == 0 {
+            malformed!("there's no main module");
+        }
+        for _ in 0..reader.usize()? {
+            let function = reader.function()?;
+            reader.functions.push(Rc::new(function));
+        }
+
+        let mut modules = Vec::new();
+        for _ in 0..reader.modules {
+            let name = reader.string()?;
+            let file = reader.usize()?;
+            if file >= reader.files.len() {
+                malformed!("file {file} of module {name} is not in the file table");
+            }
+            let mut globals = Vec::new();
+            for _ in 0..reader.usize()? {
+                globals.push(reader.string()?);
+            }
+            let script = Rc::new(reader.chunk()?);
+            let mut functions = HashMap::new();
+            for _ in 0..reader.usize()? {
+                let name = reader.string()?;
+                functions.insert(name, reader.function_index()?);
+            }
+            modules.push(Module {
+                name,
+                file,
+                functions,
+                globals,
+                script,
+            });
+        }
+        if reader.position != bytes.len() {
+            malformed!("trailing bytes after the module table");
+        }
+        let bytecode = Bytecode { modules };
+        bytecode.verify().map_err(DecodeError::Unverifiable)?;
+        Ok((bytecode, sources))
+    }
+
+    pub fn is_serialized(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use crate::{Bytecode, Chunk, Constant, Function, Instruction, Module, NATIVE_FUNCTIONS, STACK_SIZE};
+
+// A chunk which may panic the VM or corrupt its stack, found at an instruction of it.
+#[derive(Debug)]
+pub struct VerifyError {
+    pub function: String,
+    pub index: usize,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}::{:04} {}", self.function, self.index, self.message)
+    }
+}
+
+// Stack of a frame before an instruction, relative to the frame. Heights of the stack
+// when `PrepareInvoke` are executed are kept until their `Invoke`.
+#[derive(Clone, PartialEq)]
+struct State {
+    height: usize,
+    invokes: Vec<usize>,
+}
+
+struct Verifier<'a> {
+    name: &'a str,
+    chunk: &'a Chunk,
+    modules: usize,
+    // global slots of the module which the chunk belongs to.
+    globals: usize,
+    // instructions decoded at their offsets, with offsets of the next ones.
+    instructions: Vec<Option<(Instruction, usize)>>,
+    states: Vec<Option<State>>,
+    pending: Vec<usize>,
+}
+
+macro_rules! reject {
+    ($verifier: expr, $index: expr, $($arguments: tt)*) => {
+        return Err(VerifyError {
+            function: $verifier.name.to_string(),
+            index: $index,
+            message: format!($($arguments)*),
+        })
+    };
+}
+
+// Values popped and pushed by an instruction, except invocations.
+fn effect(instruction: &Instruction) -> (usize, usize) {
+    match instruction {
+        Instruction::LoadConstant(_)
+        | Instruction::True
+        | Instruction::False
+        | Instruction::Nil
+        | Instruction::GetGlobal(_)
+        | Instruction::GetLocal(_)
+        | Instruction::Import(_) => (0, 1),
+        Instruction::Add
+        | Instruction::Subtract
+        | Instruction::Multiply
+        | Instruction::Divide
+        | Instruction::Modulo
+        | Instruction::Greater
+        | Instruction::Less
+        | Instruction::GreaterEqual
+        | Instruction::LessEqual
+        | Instruction::Equal
+        | Instruction::NotEqual => (2, 1),
+        Instruction::Negate
+        | Instruction::Not
+        | Instruction::SetGlobal(_)
+        | Instruction::SetLocal(_)
+        | Instruction::JumpIfFalse(_)
+        | Instruction::GetProperty(_) => (1, 1),
+        Instruction::Duplicate => (1, 2),
+        Instruction::DefineGlobal(_)
+        | Instruction::Print
+        | Instruction::Pop
+        | Instruction::PopJumpIfFalse(_)
+        | Instruction::Return
+        | Instruction::Throw => (1, 0),
+        Instruction::Jump(_)
+        | Instruction::PrepareInvoke
+        | Instruction::Invoke
+        | Instruction::InvokeGlobal(_)
+        | Instruction::InvokeNative(_) => (0, 0),
+    }
+}
+
+impl Verifier<'_> {
+    // Decode instructions from the start, so that jumps are checked to land on them.
+    fn decode(&mut self) -> Result<(), VerifyError> {
+        let mut offset = 0;
+        while offset < self.chunk.len() {
+            if self.chunk.try_fetch(offset).is_none() {
+                let byte = self.chunk.code()[offset];
+                reject!(
+                    self,
+                    offset,
+                    "has unknown opcode {byte} or a truncated operand"
+                );
+            }
+            let (instruction, next) = self.chunk.instruction(offset);
+            self.instructions[offset] = Some((instruction, next));
+            offset = next;
+        }
+
+        // runs of spans start from the code, in order of offsets.
+        let spans = self.chunk.spans();
+        if !self.chunk.is_empty() && spans.first().is_none_or(|(start, _)| *start != 0) {
+            reject!(self, 0, "has no span");
+        }
+        for window in spans.windows(2) {
+            if window[0].0 >= window[1].0 || window[1].0 >= self.chunk.len() {
+                reject!(self, window[1].0, "has a span out of order");
+            }
+        }
+        Ok(())
+    }
+
+    // Whether an instruction starts at the offset, or it's the end of the chunk.
+    fn is_boundary(&self, offset: usize) -> bool {
+        offset == self.chunk.len() || self.instructions.get(offset).is_some_and(Option::is_some)
+    }
+
+    // Merge the state into where control arrives, which must agree with other paths.
+    fn arrive(&mut self, from: usize, target: usize, state: State) -> Result<(), VerifyError> {
+        if target == self.chunk.len() {
+            // leaving the frame, invocations must have been completed.
+            if !state.invokes.is_empty() {
+                reject!(self, from, "leaves the frame with a pending invocation");
+            }
+            return Ok(());
+        }
+        if !self.is_boundary(target) {
+            reject!(self, from, "jumps into the middle of an instruction");
+        }
+        match &self.states[target] {
+            Some(existing) if existing.height != state.height => {
+                let (this, that) = (existing.height, state.height);
+                reject!(
+                    self,
+                    target,
+                    "is reached with stack heights {this} and {that}"
+                )
+            }
+            Some(existing) if *existing != state => {
+                reject!(
+                    self,
+                    target,
+                    "is reached with different pending invocations"
+                )
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.states[target] = Some(state);
+                self.pending.push(target);
+                Ok(())
+            }
+        }
+    }
+
+    // Lowest height of the stack while the instruction is executed, which is where a
+    // runtime error raised by it leaves the stack.
+    fn lowest(&self, index: usize) -> Option<usize> {
+        let state = self.states[index].as_ref()?;
+        match &self.instructions[index].as_ref()?.0 {
+            Instruction::Invoke | Instruction::InvokeGlobal(_) | Instruction::InvokeNative(_) => {
+                state.invokes.last().copied()
+            }
+            instruction => Some(state.height - effect(instruction).0),
+        }
+    }
+
+    fn verify(&mut self, entry: State) -> Result<usize, VerifyError> {
+        let length = self.chunk.len();
+        self.decode()?;
+        for (index, handler) in self.chunk.handlers().iter().enumerate() {
+            let bounds = [handler.start, handler.end, handler.target];
+            if handler.start > handler.end
+                || handler.target >= length
+                || !bounds.iter().all(|offset| self.is_boundary(*offset))
+            {
+                reject!(self, handler.target, "has handler {index} out of the chunk");
+            }
+        }
+        self.arrive(0, 0, entry)?;
+        // exceptions are caught with the stack truncated to the depth, and the exception
+        // pushed on it.
+        for handler in self.chunk.handlers() {
+            let state = State {
+                height: handler.depth + 1,
+                invokes: Vec::new(),
+            };
+            self.arrive(handler.target, handler.target, state)?;
+        }
+
+        let mut deepest = 0;
+        while let Some(index) = self.pending.pop() {
+            let mut state = self.states[index].clone().unwrap();
+            let (instruction, next) = self.instructions[index].clone().unwrap();
+            // values below the innermost invocation are never popped before it completes.
+            let floor = state.invokes.last().copied().unwrap_or(0);
+            let (pops, pushes) = effect(&instruction);
+            if state.height < floor + pops {
+                reject!(
+                    self,
+                    index,
+                    "pops {pops} values from {} on the stack",
+                    state.height
+                );
+            }
+            match instruction {
+                Instruction::LoadConstant(constant) | Instruction::GetProperty(constant) => {
+                    let Some(value) = self.chunk.constants().get(constant) else {
+                        reject!(self, index, "loads constant {constant} out of the pool");
+                    };
+                    match (&instruction, value) {
+                        (Instruction::LoadConstant(_), _) | (_, Constant::String(_)) => {}
+                        _ => reject!(self, index, "gets a property by {value:?}"),
+                    }
+                }
+                Instruction::GetLocal(slot) | Instruction::SetLocal(slot)
+                    if slot >= state.height =>
+                {
+                    reject!(self, index, "accesses slot {slot} out of {}", state.height);
+                }
+                Instruction::DefineGlobal(slot)
+                | Instruction::GetGlobal(slot)
+                | Instruction::SetGlobal(slot)
+                | Instruction::InvokeGlobal(slot)
+                    if slot >= self.globals =>
+                {
+                    reject!(
+                        self,
+                        index,
+                        "accesses global {slot} out of {}",
+                        self.globals
+                    );
+                }
+                Instruction::InvokeNative(native) if native >= NATIVE_FUNCTIONS.len() => {
+                    reject!(
+                        self,
+                        index,
+                        "invokes native function {native} out of the VM"
+                    );
+                }
+                Instruction::Import(module) if module >= self.modules => {
+                    reject!(self, index, "imports module {module} out of the bytecode");
+                }
+                _ => {}
+            }
+
+            state.height = state.height - pops + pushes;
+            match instruction {
+                Instruction::PrepareInvoke => state.invokes.push(state.height),
+                // the callee and arguments are replaced by the returned value.
+                Instruction::Invoke => match state.invokes.pop() {
+                    Some(height) if height < state.height => state.height = height + 1,
+                    Some(_) => reject!(self, index, "invokes nothing"),
+                    None => reject!(self, index, "invokes without PrepareInvoke"),
+                },
+                // the arguments are replaced by the returned value.
+                Instruction::InvokeGlobal(_) | Instruction::InvokeNative(_) => {
+                    match state.invokes.pop() {
+                        Some(height) => state.height = height + 1,
+                        None => reject!(self, index, "invokes without PrepareInvoke"),
+                    }
+                }
+                _ => {}
+            }
+            deepest = deepest.max(state.height);
+            if deepest > STACK_SIZE {
+                reject!(self, index, "grows the stack over {STACK_SIZE} values");
+            }
+
+            if let Some(offset) = instruction.offset() {
+                let target = (index as isize).wrapping_add(offset);
+                if !(0..=length as isize).contains(&target) {
+                    reject!(self, index, "jumps to {target} out of the chunk");
+                }
+                self.arrive(index, target as usize, state.clone())?;
+            }
+            match instruction {
+                Instruction::Return if !state.invokes.is_empty() => {
+                    reject!(self, index, "returns with a pending invocation");
+                }
+                Instruction::Jump(_) | Instruction::Return | Instruction::Throw => {}
+                _ => self.arrive(index, next, state)?,
+            }
+        }
+
+        for handler in self.chunk.handlers() {
+            for index in handler.start..handler.end {
+                if self
+                    .lowest(index)
+                    .is_some_and(|lowest| lowest < handler.depth)
+                {
+                    reject!(
+                        self,
+                        index,
+                        "is below the depth {} of its handler",
+                        handler.depth
+                    );
+                }
+            }
+        }
+        Ok(deepest)
+    }
+}
+
+// Verify a chunk of the module at index `module`, entered with `arity` values on the
+// stack, returning the maximum height of the stack in its frame.
+fn verify_chunk(
+    name: &str,
+    chunk: &Chunk,
+    arity: usize,
+    modules: &[Module],
+    module: usize,
+) -> Result<usize, VerifyError> {
+    let mut verifier = Verifier {
+        name,
+        chunk,
+        modules: modules.len(),
+        globals: modules[module].globals.len(),
+        instructions: vec![None; chunk.len()],
+        states: vec![None; chunk.len()],
+        pending: Vec::new(),
+    };
+    let entry = State {
+        height: arity,
+        invokes: Vec::new(),
+    };
+    verifier.verify(entry)
+}
+
+fn verify_function(
+    function: &Rc<Function>,
+    modules: &[Module],
+    verified: &mut HashSet<*const Function>,
+) -> Result<(), VerifyError> {
+    if !verified.insert(Rc::as_ptr(function)) {
+        return Ok(());
+    }
+    if function.module >= modules.len() {
+        let message = format!("belongs to module {} out of the bytecode", function.module);
+        return Err(VerifyError {
+            function: function.name.clone(),
+            index: 0,
+            message,
+        });
+    }
+    let (chunk, module) = (&function.chunk, function.module);
+    verify_chunk(&function.name, chunk, function.arity, modules, module)?;
+    verify_constants(chunk, modules, verified)
+}
+
+fn verify_constants(
+    chunk: &Chunk,
+    modules: &[Module],
+    verified: &mut HashSet<*const Function>,
+) -> Result<(), VerifyError> {
+    for constant in chunk.constants() {
+        if let Constant::Function(function) = constant {
+            verify_function(function, modules, verified)?;
+        }
+    }
+    Ok(())
+}
+
+impl Bytecode {
+    // Verify every chunk, so that the VM never indexes out of bounds or loses track of
+    // frames however the bytecode is made. Stack heights are checked to agree along all
+    // paths of control flow, where each instruction finds enough values on the stack.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let modules = &self.modules;
+        let mut verified = HashSet::new();
+        for (index, module) in modules.iter().enumerate() {
+            verify_chunk(&module.name, &module.script, 0, modules, index)?;
+            verify_constants(&module.script, modules, &mut verified)?;
+            for name in module.function_names() {
+                verify_function(&module.functions[name], modules, &mut verified)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,77 +1,198 @@
 use codespan_reporting::diagnostic::Label;
 use phf::phf_map;
 
-use crate::{Diagnostic, Span};
+use crate::{Diagnostic, FileId, Span};
 
 struct ErrorInfo {
     message: &'static str,
     explanation: &'static str,
+    // long-form explanation with examples, shown by `rlox --explain`.
+    details: &'static str,
 }
 
 static ERROR_TABLE: phf::Map<&'static str, ErrorInfo> = phf_map! {
     "E0001" => ErrorInfo {
         message: "Unrecognized token",
         explanation: "invalid token encountered here",
+        details: include_str!("../explanations/E0001.md"),
     },
     "E0002" => ErrorInfo {
         message: "Unparsable float literal",
         explanation: "this float value may be valid, but cannot be parsed as f64",
+        details: include_str!("../explanations/E0002.md"),
     },
     "E0003" => ErrorInfo {
         message: "Early EOF",
         explanation: "expect some token (i.g. Semicolon) after this, got EOF",
+        details: include_str!("../explanations/E0003.md"),
     },
     "E0004" => ErrorInfo {
         message: "Invalid prefix expression",
         explanation: "this token cannot be prefix of an expression",
+        details: include_str!("../explanations/E0004.md"),
     },
     "E0005" => ErrorInfo {
         message: "Unexpected token",
         explanation: "this token is shouldn't be placed here",
+        details: include_str!("../explanations/E0005.md"),
     },
     "E0006" => ErrorInfo {
         message: "Stack overflow",
         explanation: "this operation caused VM stack overflow",
+        details: include_str!("../explanations/E0006.md"),
     },
     "E0007" => ErrorInfo {
         message: "Stack underflow",
         explanation: "this operation caused VM stack underflow",
+        details: include_str!("../explanations/E0007.md"),
     },
     "E0008" => ErrorInfo {
         message: "Invalid arithmetic operands",
         explanation: "this operation can only be applied to numbers",
+        details: include_str!("../explanations/E0008.md"),
     },
     "E0009" => ErrorInfo {
         message: "Invalid addition/concatenation operands",
         explanation: "this operation can only be applied to numbers or strings",
+        details: include_str!("../explanations/E0009.md"),
     },
     "E0010" => ErrorInfo {
         message: "Invalid identifier",
         explanation: "this token cannot be used as an identifier",
+        details: include_str!("../explanations/E0010.md"),
     },
     "E0011" => ErrorInfo {
         message: "Multiple definition",
         explanation: "this variable is already defined previously",
+        details: include_str!("../explanations/E0011.md"),
     },
     "E0012" => ErrorInfo {
         message: "Undefined variable",
         explanation: "this variable is never defined",
+        details: include_str!("../explanations/E0012.md"),
     },
     "E0013" => ErrorInfo {
         message: "Invalid assignment target",
         explanation: "the left operand is not assignable",
+        details: include_str!("../explanations/E0013.md"),
     },
     "E0014" => ErrorInfo {
         message: "Invalid invocation target",
         explanation: "this target is not invokable",
+        details: include_str!("../explanations/E0014.md"),
     },
     "E0015" => ErrorInfo {
         message: "Undefined function",
         explanation: "no function matches this invocation",
+        details: include_str!("../explanations/E0015.md"),
     },
     "E0016" => ErrorInfo {
         message: "Invocation arguments mismatch",
         explanation: "the number of arguments is not match",
+        details: include_str!("../explanations/E0016.md"),
+    },
+    "E0017" => ErrorInfo {
+        message: "Unsupported capture",
+        explanation: "local variables of enclosing functions cannot be captured",
+        details: include_str!("../explanations/E0017.md"),
+    },
+    "E0018" => ErrorInfo {
+        message: "Uncaught exception",
+        explanation: "this exception is thrown but never caught",
+        details: include_str!("../explanations/E0018.md"),
+    },
+    "E0019" => ErrorInfo {
+        message: "Unresolved import",
+        explanation: "this module cannot be loaded",
+        details: include_str!("../explanations/E0019.md"),
+    },
+    "E0020" => ErrorInfo {
+        message: "Cyclic import",
+        explanation: "this module is already being imported",
+        details: include_str!("../explanations/E0020.md"),
+    },
+    "E0021" => ErrorInfo {
+        message: "Invalid property access",
+        explanation: "this value has no properties",
+        details: include_str!("../explanations/E0021.md"),
+    },
+    "E0022" => ErrorInfo {
+        message: "Unterminated string",
+        explanation: "missing closing quote of this string",
+        details: include_str!("../explanations/E0022.md"),
+    },
+    "E0023" => ErrorInfo {
+        message: "Self-referencing initializer",
+        explanation: "this variable is read in its own initializer",
+        details: include_str!("../explanations/E0023.md"),
+    },
+    "E0024" => ErrorInfo {
+        message: "Duplicate local variable",
+        explanation: "this variable is already declared in the same scope",
+        details: include_str!("../explanations/E0024.md"),
+    },
+    "E0025" => ErrorInfo {
+        message: "Return outside function",
+        explanation: "top-level code cannot return",
+        details: include_str!("../explanations/E0025.md"),
+    },
+    "E0026" => ErrorInfo {
+        message: "Return value from initializer",
+        explanation: "initializers cannot return a value",
+        details: include_str!("../explanations/E0026.md"),
+    },
+    "E0027" => ErrorInfo {
+        message: "Unknown type",
+        explanation: "this is not a type",
+        details: include_str!("../explanations/E0027.md"),
+    },
+    "E0028" => ErrorInfo {
+        message: "Mismatched types",
+        explanation: "this value is not of the annotated type",
+        details: include_str!("../explanations/E0028.md"),
+    },
+    "E0029" => ErrorInfo {
+        message: "Too many slots",
+        explanation: "this is over the limit of operands",
+        details: include_str!("../explanations/E0029.md"),
+    },
+    "E0030" => ErrorInfo {
+        message: "Unsupported feature",
+        explanation: "this is parsed but cannot be compiled yet",
+        details: include_str!("../explanations/E0030.md"),
+    },
+};
+
+static WARNING_TABLE: phf::Map<&'static str, ErrorInfo> = phf_map! {
+    "W0001" => ErrorInfo {
+        message: "Unused variable",
+        explanation: "this variable is never read",
+        details: include_str!("../explanations/W0001.md"),
+    },
+    "W0002" => ErrorInfo {
+        message: "Unused parameter",
+        explanation: "this parameter is never read",
+        details: include_str!("../explanations/W0002.md"),
+    },
+    "W0003" => ErrorInfo {
+        message: "Unreachable code",
+        explanation: "any code following this is unreachable",
+        details: include_str!("../explanations/W0003.md"),
+    },
+    "W0004" => ErrorInfo {
+        message: "Shadowed variable",
+        explanation: "this variable shadows another one of an enclosing scope",
+        details: include_str!("../explanations/W0004.md"),
+    },
+    "W0005" => ErrorInfo {
+        message: "Constant condition",
+        explanation: "this condition never changes",
+        details: include_str!("../explanations/W0005.md"),
+    },
+    "W0006" => ErrorInfo {
+        message: "Assignment to undefined global",
+        explanation: "this global variable is never defined",
+        details: include_str!("../explanations/W0006.md"),
     },
 };
 
@@ -80,7 +201,36 @@ pub fn raise(error_code: &'static str, span: Span) -> Diagnostic {
     Diagnostic::error()
         .with_code(error_code)
         .with_message(info.message)
-        .with_labels(vec![Label::primary((), span).with_message(info.explanation)])
+        .with_labels(vec![
+            Label::primary(span.file, span.range).with_message(info.explanation)
+        ])
+}
+
+pub fn warn(warning_code: &'static str, span: Span) -> Diagnostic {
+    let info = &WARNING_TABLE[warning_code];
+    Diagnostic::warning()
+        .with_code(warning_code)
+        .with_message(info.message)
+        .with_labels(vec![
+            Label::primary(span.file, span.range).with_message(info.explanation)
+        ])
+}
+
+pub fn is_warning(code: &str) -> bool {
+    WARNING_TABLE.contains_key(code)
+}
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    ERROR_TABLE
+        .get(code)
+        .or_else(|| WARNING_TABLE.get(code))
+        .map(|info| info.details)
+}
+
+// Secondary labels may point into other files than the primary one, e.g. the declaration
+// of a function in another module.
+pub fn secondary(span: Span, message: impl Into<String>) -> Label<FileId> {
+    Label::secondary(span.file, span.range).with_message(message)
 }
 
 #[macro_export]
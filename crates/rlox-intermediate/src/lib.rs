@@ -10,10 +10,11 @@ mod bytecode;
 pub mod errors;
 mod utility;
 
-// Lox language has no multi-source support, so we'll just use
-// [`codespan_reporting::files::SimpleFile`] to represent source file.
-//
-// Thus, the [`FileId`] is unit type because there's no need for an id;
-type FileId = ();
+// Imported modules are loaded from other source files, so every [`Span`] carries the id
+// of its file, which is an index into [`SourceMap`].
+pub type FileId = usize;
 pub type Diagnostic = codespan_reporting::diagnostic::Diagnostic<FileId>;
+pub type Severity = codespan_reporting::diagnostic::Severity;
 pub type DiagnosableResult<T = ()> = Result<T, Box<Diagnostic>>;
+// Passes which recover from errors report all of them at once.
+pub type DiagnosticsResult<T = ()> = Result<T, Vec<Diagnostic>>;
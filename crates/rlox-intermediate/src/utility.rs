@@ -1,6 +1,7 @@
+pub use reporter::*;
 pub use source::*;
 pub use spanned::*;
 
+mod reporter;
 mod source;
 mod spanned;
-
@@ -0,0 +1,36 @@
+use codespan_reporting::term;
+use codespan_reporting::term::Config;
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+
+use crate::{Diagnostic, SourceMap};
+
+pub struct Reporter {
+    writer: StandardStream,
+    config: Config,
+}
+
+impl Reporter {
+    pub fn new() -> Self {
+        Self {
+            writer: StandardStream::stderr(ColorChoice::Always),
+            config: Config::default(),
+        }
+    }
+
+    // Labels of the diagnostic are rendered against the files they point to.
+    pub fn report(&self, sources: &SourceMap, diagnostic: &Diagnostic) {
+        term::emit(
+            &mut self.writer.lock(),
+            &self.config,
+            sources.files(),
+            diagnostic,
+        )
+        .unwrap();
+    }
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
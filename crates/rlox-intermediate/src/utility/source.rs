@@ -1,51 +1,50 @@
-use std::fmt::Display;
-use std::ops::Deref;
+use codespan_reporting::files::SimpleFiles;
 
-use codespan_reporting::files::SimpleFile;
-use codespan_reporting::term;
-use codespan_reporting::term::Config;
-use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use crate::FileId;
 
-use crate::Diagnostic;
-
-pub struct DiagnosableSource<N, S> {
-    writer: StandardStream,
-    config: Config,
-    file: SimpleFile<N, S>,
+// All source files loaded, including the main script and imported modules.
+pub struct SourceMap {
+    files: SimpleFiles<String, String>,
+    count: usize,
 }
 
-impl<N, S> DiagnosableSource<N, S>
-where
-    N: Display + Clone,
-    S: AsRef<str>,
-{
-    pub fn new(name: N, source: S) -> Self {
+impl SourceMap {
+    pub fn new() -> Self {
         Self {
-            writer: StandardStream::stderr(ColorChoice::Always),
-            config: Config::default(),
-            file: SimpleFile::new(name, source),
+            files: SimpleFiles::new(),
+            count: 0,
         }
     }
 
-    pub fn diagnose(&mut self, diagnostic: &Diagnostic) {
-        term::emit(
-            &mut self.writer.lock(),
-            &self.config,
-            &self.file,
-            diagnostic,
-        )
-        .unwrap();
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.count += 1;
+        self.files.add(name.into(), source.into())
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        self.files.get(file).unwrap().name()
+    }
+
+    pub fn source(&self, file: FileId) -> &str {
+        self.files.get(file).unwrap().source()
     }
-}
 
-impl<N, S> Deref for DiagnosableSource<N, S>
-where
-    N: Display,
-    S: AsRef<str>,
-{
-    type Target = str;
+    // files are numbered from 0, in the order they are added.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn files(&self) -> &SimpleFiles<String, String> {
+        &self.files
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        self.file.source().as_ref()
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,7 +1,27 @@
 use std::fmt::{Debug, Formatter};
 use std::ops::{Deref, DerefMut, Range};
 
-pub type Span = Range<usize>;
+use crate::FileId;
+
+// A range of source code, in the file it belongs to.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub range: Range<usize>,
+}
+
+impl Span {
+    pub fn new(file: FileId, range: Range<usize>) -> Self {
+        Self { file, range }
+    }
+
+    // Smallest span covering both, which are expected to be in the same file.
+    pub fn merge(&self, other: &Span) -> Span {
+        let start = self.range.start.min(other.range.start);
+        let end = self.range.end.max(other.range.end);
+        Span::new(self.file, start..end)
+    }
+}
 
 pub struct Spanned<T> {
     pub value: T,
@@ -0,0 +1,102 @@
+use rlox_intermediate::*;
+
+fn error(source: &str) -> String {
+    match Bytecode::assemble(source, 0) {
+        Ok(_) => panic!("assembled unexpectedly:\n{source}"),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn assembly_round_trips() {
+    let source = r#".module "main"
+
+.lambda #0 "inner" 0
+    LoadConstant "tab\t, quote\" and \u{7f}"
+    Return
+
+.function #1 "outer" 1
+L0:
+    GetLocal 0
+    SetGlobal "x"
+    PopJumpIfFalse L1
+    LoadConstant #0
+    Return
+L1:
+    LoadConstant -0
+    LoadConstant NaN
+    LoadConstant -inf
+    LoadConstant 0.1
+    Jump L0
+    .handler L0 L1 L1 1
+
+.script
+    Import 0
+    DefineGlobal "y"
+    GetGlobal "x"
+    Pop
+"#;
+    let bytecode = Bytecode::assemble(source, 0).unwrap();
+    // globals are allocated slots in order of their first occurrences, where named
+    // functions occur at the ends of their chunks.
+    assert_eq!(bytecode.modules[0].globals, ["x", "outer", "y"]);
+    assert_eq!(bytecode.disassemble(&SourceMap::new()), source);
+}
+
+#[test]
+fn comments_and_source_lines() {
+    let source = ".module \"main\" ; the main module\n\n.script\n  ; nothing\n    Nil ; \";\"\n";
+    let bytecode = Bytecode::assemble(source, 0).unwrap();
+    let mut sources = SourceMap::new();
+    sources.add("main.loxa", source);
+    assert_eq!(
+        bytecode.disassemble(&sources),
+        ".module \"main\"\n\n.script\n    ; 5: Nil ; \";\"\n    Nil\n",
+    );
+}
+
+#[test]
+fn invalid_assembly_is_rejected() {
+    assert_eq!(error(""), "line 1: there's no main module");
+    assert_eq!(error("Nil"), "line 1: instruction Nil is out of any chunk");
+    assert_eq!(
+        error(".module \"main\""),
+        "line 1: module \"main\" has no .script"
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    Jump L0"),
+        "line 3: label L0 is not defined",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\nL0:\nL0:"),
+        "line 4: label L0 is already defined",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    Nop"),
+        "line 3: unknown instruction Nop",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    Pop 1"),
+        "line 3: unexpected operands of Pop",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    LoadConstant #0"),
+        "line 3: function #0 is not defined yet",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    InvokeNative \"print\""),
+        "line 3: native function \"print\" is not defined",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    Import 1"),
+        "line 3: module 1 is not defined",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n    Nil\n    GetLocal 16777216"),
+        "line 4: operand 16777216 of GetLocal is over 16777215",
+    );
+    assert_eq!(
+        error(".module \"main\"\n.script\n.function #0 \"f\" 0"),
+        "line 3: functions are expected before .script",
+    );
+}
@@ -0,0 +1,56 @@
+use rlox_intermediate::*;
+
+#[test]
+fn operands_are_widened_as_needed() {
+    let span = |line| Span::new(0, line..line + 1);
+    let mut builder = ChunkBuilder::new();
+    builder.write(Instruction::GetLocal(255), span(0));
+    builder.write(Instruction::GetLocal(256), span(0));
+    builder.write(Instruction::Jump(3), span(1));
+    builder.write(Instruction::Jump(40002), span(1));
+    builder.write(Instruction::Jump(-2), span(2));
+    for _ in 0..40000 {
+        builder.write(Instruction::Nil, span(3));
+    }
+    builder.handlers.push(Handler {
+        start: 2,
+        end: 5,
+        target: 5,
+        depth: 0,
+    });
+    let chunk = builder.build();
+
+    let opcodes = [
+        (Opcode::GetLocal, 255, 0),
+        (Opcode::GetLocalLong, 256, 2),
+        (Opcode::Jump, 11, 6),
+        (Opcode::JumpLong, 40008, 9),
+        (Opcode::Jump, -8, 14),
+    ];
+    for (opcode, operand, offset) in opcodes {
+        let (fetched, fetched_operand, _) = chunk.fetch(offset);
+        assert_eq!((fetched, fetched_operand), (opcode, operand));
+    }
+    assert_eq!(chunk.len(), 17 + 40000);
+    assert_eq!(chunk.instructions().count(), 40005);
+
+    // spans are kept in runs, handlers in offsets.
+    let runs = chunk
+        .spans()
+        .iter()
+        .map(|(offset, _)| *offset)
+        .collect::<Vec<_>>();
+    assert_eq!(runs, [0, 6, 14, 17]);
+    assert_eq!(chunk.span(40000).range, 3..4);
+    let handler = &chunk.handlers()[0];
+    assert_eq!((handler.start, handler.end, handler.target), (6, 17, 17));
+}
+
+#[test]
+fn opcodes_are_decoded_from_their_bytes() {
+    for byte in 0..=u8::MAX {
+        if let Some(opcode) = Opcode::from_byte(byte) {
+            assert_eq!(opcode as u8, byte);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::Path;
+
+use rlox_intermediate::errors;
+
+// Collect string literals like "E0001" or "W0001" from all Rust sources under `path`.
+fn collect_codes(path: &Path, codes: &mut Vec<String>) {
+    if path.is_dir() {
+        for entry in fs::read_dir(path).unwrap() {
+            collect_codes(&entry.unwrap().path(), codes);
+        }
+        return;
+    }
+    if path.extension().is_none_or(|extension| extension != "rs") {
+        return;
+    }
+    let source = fs::read(path).unwrap();
+    for window in source.windows(7) {
+        if let [b'"', kind @ (b'E' | b'W'), number @ .., b'"'] = window {
+            if number.iter().all(u8::is_ascii_digit) {
+                let number = String::from_utf8_lossy(number);
+                codes.push(format!("{}{number}", *kind as char));
+            }
+        }
+    }
+}
+
+#[test]
+fn every_raised_code_is_explained() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let mut codes = Vec::new();
+    collect_codes(&root.join("src"), &mut codes);
+    collect_codes(&root.join("crates"), &mut codes);
+    assert!(!codes.is_empty(), "no code is found in the workspace");
+
+    for code in codes {
+        let details = errors::explain(&code);
+        assert!(details.is_some(), "{code} has no explanation");
+        assert!(details.unwrap().contains("```lox"), "{code} has no example");
+    }
+}
@@ -0,0 +1,104 @@
+use rlox_intermediate::*;
+
+fn verify(source: &str) -> Result<(), String> {
+    let bytecode = Bytecode::assemble(source, 0).unwrap();
+    bytecode.verify().map_err(|error| error.to_string())
+}
+
+#[test]
+fn consistent_chunks_are_verified() {
+    let source = r#".module "main"
+
+.function #0 "f" 2
+    GetLocal 0
+    GetLocal 1
+    Add
+    Return
+
+.script
+L0:
+    PrepareInvoke
+    LoadConstant 1
+    LoadConstant 2
+    InvokeGlobal "f"
+    JumpIfFalse L1
+    Pop
+    LoadConstant "x"
+    Throw
+L1:
+    Print
+    Jump L3
+L2:
+    Print
+L3:
+    Nil
+    Duplicate
+    Pop
+    Pop
+    .handler L0 L1 L2 0
+"#;
+    assert_eq!(verify(source), Ok(()));
+}
+
+#[test]
+fn inconsistent_chunks_are_rejected() {
+    let script = |body: &str| format!(".module \"main\"\n.script\n{body}");
+    assert_eq!(
+        verify(&script("    Pop")),
+        Err(String::from("main::0000 pops 1 values from 0 on the stack")),
+    );
+    assert_eq!(
+        verify(&script("    Nil\n    GetLocal 1")),
+        Err(String::from("main::0001 accesses slot 1 out of 1")),
+    );
+    assert_eq!(
+        verify(&script(
+            "    True\n    PopJumpIfFalse L0\n    Nil\nL0:\n    Pop"
+        )),
+        Err(String::from(
+            "main::0005 is reached with stack heights 0 and 1"
+        )),
+    );
+    assert_eq!(
+        verify(&script("    PrepareInvoke\n    Nil\n    Pop\n    Pop")),
+        Err(String::from("main::0003 pops 1 values from 0 on the stack")),
+    );
+    assert_eq!(
+        verify(&script("    PrepareInvoke")),
+        Err(String::from(
+            "main::0000 leaves the frame with a pending invocation"
+        )),
+    );
+    assert_eq!(
+        verify(&script("    Invoke")),
+        Err(String::from("main::0000 invokes without PrepareInvoke")),
+    );
+    assert_eq!(
+        verify(&script("    InvokeNative \"clock\"")),
+        Err(String::from("main::0000 invokes without PrepareInvoke")),
+    );
+    assert_eq!(
+        verify(&script("    GetProperty 1")),
+        Err(String::from("main::0000 pops 1 values from 0 on the stack")),
+    );
+    assert_eq!(
+        verify(&script("    Nil\n    GetProperty 1")),
+        Err(String::from("main::0001 gets a property by Number(1.0)")),
+    );
+    assert_eq!(
+        verify(&script(
+            "L0:\n    Nil\nL1:\n    Throw\nL2:\n    Pop\n    .handler L0 L1 L2 1",
+        )),
+        Err(String::from(
+            "main::0000 is below the depth 1 of its handler"
+        )),
+    );
+}
+
+#[test]
+fn frames_deeper_than_the_stack_are_rejected() {
+    let body = "    Nil\n".repeat(STACK_SIZE + 1);
+    let source = format!(".module \"main\"\n.script\n{body}");
+    let error = format!("main::{STACK_SIZE:04} grows the stack over {STACK_SIZE} values");
+    assert_eq!(verify(&source), Err(error));
+}
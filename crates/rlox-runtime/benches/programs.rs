@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use rlox_analyzer::linter::Lints;
+use rlox_analyzer::loader;
+use rlox_intermediate::*;
+use rlox_runtime::VirtualMachine;
+
+// Runs of each program, whose median and best times are reported.
+const RUNS: usize = 10;
+
+fn compile(path: &Path) -> Bytecode {
+    let mut sources = SourceMap::new();
+    let file = sources.add(
+        path.display().to_string(),
+        fs::read_to_string(path).unwrap(),
+    );
+    let lints = Lints::new();
+    match loader::load(&mut sources, file, Some(path), &lints, &mut Vec::new()) {
+        Ok(bytecode) => bytecode,
+        Err(diagnostics) => panic!("cannot compile {}: {diagnostics:?}", path.display()),
+    }
+}
+
+// Time of running the program only, excluding compilation.
+fn run(path: &Path) -> Duration {
+    let mut vm = VirtualMachine::new(compile(path));
+    let started = Instant::now();
+    if let Err(diagnostic) = vm.run() {
+        panic!("{} failed: {diagnostic:?}", path.display());
+    }
+    started.elapsed()
+}
+
+// Benchmarks of the VM on the programs under `benches/programs`, run by
+// `cargo bench -p rlox-runtime`, or `cargo bench -p rlox-runtime -- fib` for some of them.
+fn main() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/programs");
+    let mut paths = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    // flags such as `--bench` are passed by cargo.
+    let filters = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<_>>();
+
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter)) {
+            continue;
+        }
+        let mut times = (0..RUNS).map(|_| run(&path)).collect::<Vec<_>>();
+        times.sort();
+        println!(
+            "{name:<12} median {:>12.3?}    best {:>12.3?}",
+            times[RUNS / 2],
+            times[0]
+        );
+    }
+}
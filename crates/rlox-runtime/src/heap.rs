@@ -1,27 +1,33 @@
 use std::collections::HashMap;
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use rlox_intermediate::Function;
 
 pub use reference::*;
 
 mod reference;
 
 pub struct Heap {
-    allocated: Vec<Reference<()>>,
+    // the newest object, through which all objects are linked.
+    objects: Option<NonNull<Header>>,
     string_pool: HashMap<String, Reference<String>>,
+    // functions by their addresses, so that each function has a single object.
+    function_pool: HashMap<*const Function, Reference<Rc<Function>>>,
 }
 
 impl Heap {
     pub fn new() -> Self {
         Self {
-            allocated: Vec::new(),
+            objects: None,
             string_pool: HashMap::new(),
+            function_pool: HashMap::new(),
         }
     }
 
-    pub fn spawn<T: 'static>(&mut self, value: T) -> Reference<T> {
-        let reference = unsafe { Reference::new(Box::into_raw(Box::new(value))) };
-        unsafe {
-            self.allocated.push(reference.clone().cast());
-        }
+    pub fn spawn<T: Object>(&mut self, value: T) -> Reference<T> {
+        let reference = Reference::allocate(value, self.objects);
+        self.objects = Some(reference.as_raw());
         reference
     }
 
@@ -33,20 +39,37 @@ impl Heap {
         self.string_pool.insert(value, reference.clone());
         reference
     }
+
+    pub fn spawn_function(&mut self, function: &Rc<Function>) -> Reference<Rc<Function>> {
+        if let Some(reference) = self.function_pool.get(&Rc::as_ptr(function)) {
+            return reference.clone();
+        }
+        let reference = self.spawn(Rc::clone(function));
+        self.function_pool
+            .insert(Rc::as_ptr(function), reference.clone());
+        reference
+    }
 }
 
 impl Drop for Heap {
     fn drop(&mut self) {
-        for allocation in &mut self.allocated {
+        let mut next = self.objects;
+        while let Some(pointer) = next {
+            let object = unsafe { Reference::<()>::from_raw(pointer) };
+            next = object.header().next();
             #[cfg(feature = "gc-sanitizer")]
-            {
-                if let Some(string) = allocation.downcast_ref::<String>() {
-                    println!("-- GC finalize: \"{string}\"");
-                } else {
-                    println!("-- GC finalize: {allocation:?}")
+            match object.kind() {
+                Kind::String => {
+                    let string = object.downcast_ref::<String>().unwrap();
+                    println!("-- GC finalize: \"{string}\"")
+                }
+                Kind::Function => {
+                    let function = object.downcast_ref::<Rc<Function>>().unwrap();
+                    println!("-- GC finalize: {function:?}")
                 }
+                Kind::Error => println!("-- GC finalize: {object:?}"),
             }
-            unsafe { allocation.finalize() }
+            unsafe { object.finalize() }
         }
     }
 }
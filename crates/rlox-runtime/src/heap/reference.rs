@@ -1,41 +1,150 @@
-use std::any::Any;
+use std::cell::Cell;
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::ptr;
 use std::ptr::NonNull;
+use std::rc::Rc;
 
+use rlox_intermediate::{Diagnostic, Function};
+
+// Kinds of objects on the heap, which tag their headers. Closures, classes and instances
+// are still unsupported (E0017 and E0030) and Lox has no lists, so kinds are added along
+// with those features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    String,
+    Function,
+    Error,
+}
+
+// Types of objects allocated on the heap, each of its own kind.
+pub trait Object: 'static {
+    const KIND: Kind;
+}
+
+impl Object for String {
+    const KIND: Kind = Kind::String;
+}
+
+// functions are shared with the bytecode, and only their handles are on the heap.
+impl Object for Rc<Function> {
+    const KIND: Kind = Kind::Function;
+}
+
+impl Object for Diagnostic {
+    const KIND: Kind = Kind::Error;
+}
+
+// Header of every object on the heap, which is followed by the object itself. Objects are
+// linked through their headers from the newest one, and marked by the GC when reachable.
+pub struct Header {
+    kind: Kind,
+    marked: Cell<bool>,
+    next: Option<NonNull<Header>>,
+}
+
+impl Header {
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn is_marked(&self) -> bool {
+        self.marked.get()
+    }
+
+    pub fn mark(&self) {
+        self.marked.set(true);
+    }
+
+    pub fn unmark(&self) {
+        self.marked.set(false);
+    }
+
+    pub fn next(&self) -> Option<NonNull<Header>> {
+        self.next
+    }
+}
+
+#[repr(C)]
+struct Allocation<T> {
+    header: Header,
+    object: T,
+}
+
+// A reference to an object on the heap, or to one of any kind if `T` is `()`.
 #[derive(Eq, PartialEq)]
 pub struct Reference<T> {
-    pointer: NonNull<dyn Any>,
+    pointer: NonNull<Header>,
     _marker: PhantomData<T>,
 }
 
-impl<T: 'static> Reference<T> {
-    pub(super) unsafe fn new(pointer: *mut T) -> Self {
+impl<T: Object> Reference<T> {
+    // Allocate the object, linked before `next`. It's freed by `finalize`.
+    pub(super) fn allocate(object: T, next: Option<NonNull<Header>>) -> Self {
+        let header = Header {
+            kind: T::KIND,
+            marked: Cell::new(false),
+            next,
+        };
+        let allocation = Box::leak(Box::new(Allocation { header, object }));
         Self {
-            pointer: NonNull::new_unchecked(pointer as *mut dyn Any),
+            pointer: NonNull::from(allocation).cast(),
             _marker: PhantomData,
         }
     }
+}
 
-    pub(super) unsafe fn finalize(&mut self) {
-        ptr::drop_in_place(self.pointer.as_mut())
-    }
-
-    pub unsafe fn cast<U: 'static>(self) -> Reference<U> {
-        Reference::<U> {
-            pointer: self.pointer,
+impl<T> Reference<T> {
+    pub(crate) unsafe fn from_raw(pointer: NonNull<Header>) -> Self {
+        Self {
+            pointer,
             _marker: PhantomData,
         }
     }
 
-    pub fn downcast_ref<U: 'static>(&self) -> Option<&U> {
-        unsafe { self.pointer.as_ref().downcast_ref() }
+    pub(crate) fn as_raw(&self) -> NonNull<Header> {
+        self.pointer
+    }
+
+    pub fn header(&self) -> &Header {
+        unsafe { self.pointer.as_ref() }
     }
 
-    pub fn downcast_mut<U: 'static>(&mut self) -> Option<&mut U> {
-        unsafe { self.pointer.as_mut().downcast_mut() }
+    pub fn kind(&self) -> Kind {
+        self.header().kind
+    }
+
+    // the same object as one of any kind.
+    pub fn erase(&self) -> Reference<()> {
+        unsafe { Reference::from_raw(self.pointer) }
+    }
+
+    // the same object as one of kind `U`, if it is.
+    pub fn downcast<U: Object>(&self) -> Option<Reference<U>> {
+        (self.kind() == U::KIND).then(|| unsafe { Reference::from_raw(self.pointer) })
+    }
+
+    pub fn downcast_ref<U: Object>(&self) -> Option<&U> {
+        (self.kind() == U::KIND).then(|| unsafe { self.object() })
+    }
+
+    unsafe fn object<U>(&self) -> &U {
+        &(*self.pointer.cast::<Allocation<U>>().as_ptr()).object
+    }
+
+    // Free the object by its kind, which must not be referred to any more.
+    pub(super) unsafe fn finalize(self) {
+        let pointer = self.pointer;
+        match self.kind() {
+            Kind::String => drop(Box::from_raw(pointer.cast::<Allocation<String>>().as_ptr())),
+            Kind::Function => drop(Box::from_raw(
+                pointer.cast::<Allocation<Rc<Function>>>().as_ptr(),
+            )),
+            Kind::Error => drop(Box::from_raw(
+                pointer.cast::<Allocation<Diagnostic>>().as_ptr(),
+            )),
+        }
     }
 }
 
@@ -48,23 +157,23 @@ impl<T> Clone for Reference<T> {
     }
 }
 
-impl<T> Deref for Reference<T> {
+impl<T: Object> Deref for Reference<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { self.pointer.cast().as_ref() }
+        unsafe { self.object() }
     }
 }
 
-impl<T> DerefMut for Reference<T> {
+impl<T: Object> DerefMut for Reference<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { self.pointer.cast().as_mut() }
+        unsafe { &mut (*self.pointer.cast::<Allocation<T>>().as_ptr()).object }
     }
 }
 
 impl<T> AsRef<str> for Reference<T>
 where
-    T: AsRef<str>,
+    T: Object + AsRef<str>,
 {
     fn as_ref(&self) -> &str {
         self.deref().as_ref()
@@ -73,6 +182,6 @@ where
 
 impl<T> Debug for Reference<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<object: {:p}>", self.pointer)
+        write!(f, "<{:?} object: {:p}>", self.kind(), self.pointer)
     }
 }
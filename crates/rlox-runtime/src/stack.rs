@@ -1,19 +1,18 @@
 use std::fmt::{Debug, Formatter};
 use std::mem;
 use std::mem::MaybeUninit;
-use std::ops::{Deref, DerefMut};
-use std::slice::{Iter, IterMut};
+use std::ops::Deref;
 
 use rlox_intermediate::*;
 
-use crate::value::Value;
+use crate::value::{Unpacked, Value};
 
-pub struct Stack<T, const N: usize> {
-    data: [MaybeUninit<T>; N],
+pub struct Stack<const N: usize> {
+    data: [MaybeUninit<Value>; N],
     top: usize,
 }
 
-impl<T, const N: usize> Stack<T, N> {
+impl<const N: usize> Stack<N> {
     pub fn new() -> Self {
         Self {
             data: unsafe { mem::zeroed() },
@@ -29,16 +28,28 @@ impl<T, const N: usize> Stack<T, N> {
         self.len() == 0
     }
 
-    pub fn top(&self, span: Span) -> DiagnosableResult<&T> {
-        if self.top > 0 {
-            unsafe {
-                return Ok(self.data[self.top - 1].assume_init_ref());
-            }
+    fn values(&self) -> &[Value] {
+        unsafe { &*(&self.data[..self.top] as *const [MaybeUninit<Value>] as *const [Value]) }
+    }
+
+    // value at the index from the bottom, which is expected to be on the stack.
+    pub fn get(&self, index: usize) -> Value {
+        self.values()[index].clone()
+    }
+
+    pub fn set(&mut self, index: usize, value: Value) {
+        assert!(index < self.top, "slot {index} is out of the stack");
+        unsafe { *self.data[index].assume_init_mut() = value }
+    }
+
+    pub fn top(&self, span: impl FnOnce() -> Span) -> DiagnosableResult<Value> {
+        match self.values().last() {
+            Some(value) => Ok(value.clone()),
+            None => raise!("E0007", span()),
         }
-        raise!("E0007", span)
     }
 
-    pub fn try_push(&mut self, element: T) -> bool {
+    pub fn try_push(&mut self, element: Value) -> bool {
         if self.top < N {
             self.data[self.top] = MaybeUninit::new(element);
             self.top += 1;
@@ -47,81 +58,41 @@ impl<T, const N: usize> Stack<T, N> {
         false
     }
 
-    pub fn push(&mut self, element: T, span: Span) -> DiagnosableResult {
+    pub fn push(&mut self, element: Value, span: impl FnOnce() -> Span) -> DiagnosableResult {
         if !self.try_push(element) {
-            raise!("E0006", span)
+            raise!("E0006", span())
         }
         Ok(())
     }
 
-    pub fn try_pop(&mut self) -> Option<T> {
+    pub fn try_pop(&mut self) -> Option<Value> {
         if self.top > 0 {
             self.top -= 1;
-            let slot = mem::replace(&mut self.data[self.top], MaybeUninit::uninit());
-            return Some(unsafe { slot.assume_init() });
+            let value = mem::replace(&mut self.data[self.top], MaybeUninit::uninit());
+            return Some(unsafe { value.assume_init() });
         }
         None
     }
 
-    pub fn pop(&mut self, span: Span) -> DiagnosableResult<T> {
+    pub fn pop(&mut self, span: impl FnOnce() -> Span) -> DiagnosableResult<Value> {
         if let Some(value) = self.try_pop() {
             return Ok(value);
         }
-        raise!("E0007", span)
-    }
-}
-
-impl<T, const N: usize> Drop for Stack<T, N> {
-    fn drop(&mut self) {
-        while let Some(value) = self.try_pop() {
-            drop(value);
-        }
-    }
-}
-
-impl<T, const N: usize> Deref for Stack<T, N> {
-    type Target = [T];
-
-    fn deref(&self) -> &Self::Target {
-        let slice: &[T; N] = unsafe { mem::transmute(&self.data) };
-        &slice[0..self.top]
-    }
-}
-
-impl<T, const N: usize> DerefMut for Stack<T, N> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        let slice: &mut [T; N] = unsafe { mem::transmute(&mut self.data) };
-        &mut slice[0..self.top]
-    }
-}
-
-impl<'a, T, const N: usize> IntoIterator for &'a Stack<T, N> {
-    type Item = &'a T;
-    type IntoIter = Iter<'a, T>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.deref().into_iter()
-    }
-}
-
-impl<'a, T, const N: usize> IntoIterator for &'a mut Stack<T, N> {
-    type Item = &'a mut T;
-    type IntoIter = IterMut<'a, T>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.deref_mut().into_iter()
+        raise!("E0007", span())
     }
 }
 
-impl<const N: usize> Debug for Stack<Value, N> {
+impl<const N: usize> Debug for Stack<N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "   ")?;
-        for element in self {
-            match element {
-                Value::Number(number) => write!(f, "  [ {number} ]"),
-                Value::Boolean(boolean) => write!(f, "  [ {boolean} ]"),
-                Value::Nil => write!(f, "  [ nil ]"),
-                Value::String(string) => write!(f, "  [ \"{}\" ]", string.deref()),
+        for element in self.values() {
+            match element.unpack() {
+                Unpacked::Number(number) => write!(f, "  [ {number} ]"),
+                Unpacked::Boolean(boolean) => write!(f, "  [ {boolean} ]"),
+                Unpacked::Nil => write!(f, "  [ nil ]"),
+                Unpacked::String(string) => write!(f, "  [ \"{}\" ]", string.deref()),
+                Unpacked::Function(function) => write!(f, "  [ {:?} ]", function.deref()),
+                Unpacked::Error(_) | Unpacked::Module(_) => write!(f, "  [ {element} ]"),
             }?
         }
         Ok(())
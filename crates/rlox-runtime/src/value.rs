@@ -1,52 +1,134 @@
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::ops::Deref;
+use std::rc::Rc;
+
+use rlox_intermediate::{Diagnostic, Function};
 
 use crate::heap::Reference;
 
+pub use packing::Value;
+
+mod packing;
+
+// Values by their kinds, which are packed into `Value` and matched on once unpacked.
 #[derive(Debug, Clone)]
-pub enum Value {
+pub enum Unpacked {
     Nil,
     Boolean(bool),
     Number(f64),
     String(Reference<String>),
+    Function(Reference<Rc<Function>>),
+    Error(Reference<Diagnostic>),
+    Module(usize),
 }
 
 impl Value {
-    pub fn boolean(&self) -> bool {
-        match self {
-            Value::Boolean(boolean) => *boolean,
-            Value::Nil => false,
-            _ => true,
+    pub fn nil() -> Self {
+        Self::pack(Unpacked::Nil)
+    }
+
+    pub fn module(module: usize) -> Self {
+        Self::pack(Unpacked::Module(module))
+    }
+
+    pub fn as_string(&self) -> Option<Reference<String>> {
+        match self.unpack() {
+            Unpacked::String(string) => Some(string),
+            _ => None,
         }
     }
+
+    pub fn as_function(&self) -> Option<Reference<Rc<Function>>> {
+        match self.unpack() {
+            Unpacked::Function(function) => Some(function),
+            _ => None,
+        }
+    }
+
+    pub fn as_error(&self) -> Option<Reference<Diagnostic>> {
+        match self.unpack() {
+            Unpacked::Error(diagnostic) => Some(diagnostic),
+            _ => None,
+        }
+    }
+
+    pub fn as_module(&self) -> Option<usize> {
+        match self.unpack() {
+            Unpacked::Module(module) => Some(module),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Self::pack(Unpacked::Boolean(boolean))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Self::pack(Unpacked::Number(number))
+    }
+}
+
+impl From<Reference<String>> for Value {
+    fn from(string: Reference<String>) -> Self {
+        Self::pack(Unpacked::String(string))
+    }
+}
+
+impl From<Reference<Rc<Function>>> for Value {
+    fn from(function: Reference<Rc<Function>>) -> Self {
+        Self::pack(Unpacked::Function(function))
+    }
+}
+
+impl From<Reference<Diagnostic>> for Value {
+    fn from(diagnostic: Reference<Diagnostic>) -> Self {
+        Self::pack(Unpacked::Error(diagnostic))
+    }
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Nil, Value::Nil) => true,
-            (Value::Boolean(this), Value::Boolean(that)) => this == that,
-            (Value::Number(this), Value::Number(that)) => (this - that).abs() < f64::EPSILON,
-            (Value::String(this), Value::String(that)) => {
+        match (self.unpack(), other.unpack()) {
+            (Unpacked::Nil, Unpacked::Nil) => true,
+            (Unpacked::Boolean(this), Unpacked::Boolean(that)) => this == that,
+            (Unpacked::Number(this), Unpacked::Number(that)) => this == that,
+            (Unpacked::String(this), Unpacked::String(that)) => {
                 if this == that {
                     return true;
                 }
                 this.deref() == that.deref()
             }
+            (Unpacked::Function(this), Unpacked::Function(that)) => this.erase() == that.erase(),
+            (Unpacked::Error(this), Unpacked::Error(that)) => this == that,
+            (Unpacked::Module(this), Unpacked::Module(that)) => this == that,
             _ => false,
         }
     }
 }
 
-impl Eq for Value {}
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.unpack().fmt(f)
+    }
+}
 
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Nil => write!(f, "nil"),
-            Value::Boolean(boolean) => write!(f, "{boolean}"),
-            Value::Number(number) => write!(f, "{number}"),
-            Value::String(string) => write!(f, "{}", string.deref()),
+        match self.unpack() {
+            Unpacked::Nil => write!(f, "nil"),
+            Unpacked::Boolean(boolean) => write!(f, "{boolean}"),
+            Unpacked::Number(number) => write!(f, "{number}"),
+            Unpacked::String(string) => write!(f, "{}", string.deref()),
+            Unpacked::Function(function) => write!(f, "{:?}", function.deref()),
+            Unpacked::Error(diagnostic) => match &diagnostic.code {
+                Some(code) => write!(f, "error[{code}]: {}", diagnostic.message),
+                None => write!(f, "error: {}", diagnostic.message),
+            },
+            Unpacked::Module(module) => write!(f, "<module {module}>"),
         }
     }
 }
@@ -0,0 +1,131 @@
+// Values are NaN-boxed into 8 bytes with the `nan-boxing` feature, or kept unpacked
+// otherwise. Either way they are only built and inspected through the same methods.
+#[cfg(not(feature = "nan-boxing"))]
+pub use plain::Value;
+
+#[cfg(not(feature = "nan-boxing"))]
+mod plain {
+    use crate::value::Unpacked;
+
+    #[derive(Clone)]
+    pub struct Value(Unpacked);
+
+    impl Value {
+        pub fn pack(unpacked: Unpacked) -> Self {
+            Self(unpacked)
+        }
+
+        pub fn unpack(&self) -> Unpacked {
+            self.0.clone()
+        }
+
+        pub fn as_number(&self) -> Option<f64> {
+            match self.0 {
+                Unpacked::Number(number) => Some(number),
+                _ => None,
+            }
+        }
+
+        pub fn is_truthy(&self) -> bool {
+            !matches!(self.0, Unpacked::Nil | Unpacked::Boolean(false))
+        }
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+pub use boxing::Value;
+
+#[cfg(feature = "nan-boxing")]
+mod boxing {
+    use std::ptr::NonNull;
+
+    use crate::heap::{Kind, Reference};
+    use crate::value::Unpacked;
+
+    // Numbers are kept as their bits, except NaNs which are all made the quiet NaN. Any
+    // other value is a quiet NaN with some of the bits below set, which no number has.
+    const QUIET_NAN: u64 = 0x7ffc_0000_0000_0000;
+    const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+    const NIL: u64 = QUIET_NAN | 1;
+    const FALSE: u64 = QUIET_NAN | 2;
+    const TRUE: u64 = QUIET_NAN | 3;
+    // objects also have the sign bit set, with pointers (aligned to 8 bytes) or module
+    // indices (shifted) in the low 50 bits, and their kinds in the lowest 3 bits. Kinds of
+    // heap objects are told by their headers.
+    const OBJECT: u64 = 0x8000_0000_0000_0000 | QUIET_NAN;
+    const PAYLOAD: u64 = !OBJECT;
+    const KIND: u64 = 0b111;
+    const HEAP: u64 = 0;
+    const MODULE: u64 = 1;
+
+    #[derive(Clone)]
+    pub struct Value(u64);
+
+    fn object(pointer: *const (), kind: u64) -> u64 {
+        let address = pointer as u64;
+        assert!(
+            address & (OBJECT | KIND) == 0,
+            "pointer {pointer:p} cannot be boxed"
+        );
+        OBJECT | address | kind
+    }
+
+    impl Value {
+        pub fn pack(unpacked: Unpacked) -> Self {
+            Self(match unpacked {
+                Unpacked::Nil => NIL,
+                Unpacked::Boolean(false) => FALSE,
+                Unpacked::Boolean(true) => TRUE,
+                Unpacked::Number(number) if number.is_nan() => CANONICAL_NAN,
+                Unpacked::Number(number) => number.to_bits(),
+                Unpacked::String(string) => object(string.as_raw().as_ptr() as *const (), HEAP),
+                Unpacked::Function(function) => {
+                    object(function.as_raw().as_ptr() as *const (), HEAP)
+                }
+                Unpacked::Error(diagnostic) => {
+                    object(diagnostic.as_raw().as_ptr() as *const (), HEAP)
+                }
+                Unpacked::Module(module) => {
+                    assert!(
+                        (module as u64) < PAYLOAD >> 3,
+                        "module {module} cannot be boxed"
+                    );
+                    OBJECT | ((module as u64) << 3) | MODULE
+                }
+            })
+        }
+
+        pub fn unpack(&self) -> Unpacked {
+            match self.0 {
+                NIL => Unpacked::Nil,
+                FALSE => Unpacked::Boolean(false),
+                TRUE => Unpacked::Boolean(true),
+                bits if bits & QUIET_NAN != QUIET_NAN => Unpacked::Number(f64::from_bits(bits)),
+                bits => match bits & KIND {
+                    HEAP => {
+                        let pointer = NonNull::new(self.pointer()).unwrap();
+                        let object = unsafe { Reference::<()>::from_raw(pointer) };
+                        match object.kind() {
+                            Kind::String => Unpacked::String(object.downcast().unwrap()),
+                            Kind::Function => Unpacked::Function(object.downcast().unwrap()),
+                            Kind::Error => Unpacked::Error(object.downcast().unwrap()),
+                        }
+                    }
+                    _ => Unpacked::Module(((bits & PAYLOAD) >> 3) as usize),
+                },
+            }
+        }
+
+        pub fn as_number(&self) -> Option<f64> {
+            (self.0 & QUIET_NAN != QUIET_NAN).then(|| f64::from_bits(self.0))
+        }
+
+        pub fn is_truthy(&self) -> bool {
+            self.0 != NIL && self.0 != FALSE
+        }
+
+        fn pointer<T>(&self) -> *mut T {
+            (self.0 & PAYLOAD & !KIND) as *mut T
+        }
+    }
+}
@@ -6,30 +6,42 @@ use std::time::Instant;
 
 use rlox_intermediate::*;
 
-use crate::heap::{Heap, Reference};
+use crate::heap::Heap;
 use crate::stack::Stack;
 use crate::value::Value;
 
-const STACK_SIZE: usize = 1024;
-
 type NativeFunction = fn(&mut VirtualMachine) -> Value;
 
 pub struct VirtualMachine {
     bytecode: Bytecode,
     program_count: usize,
     stack_offset: usize,
-    stack: Stack<Value, STACK_SIZE>,
+    stack: Stack<STACK_SIZE>,
     heap: Heap,
-    globals: HashMap<String, Value>,
+
+    // Module fields, globals are separated by modules and indexed by their slots, which
+    // are `None` until defined. Top-level functions are defined from the start.
+    module: usize,
+    globals: Vec<Vec<Option<Value>>>,
+    // slots of globals by names, for those accessed by name (e.g. as module properties).
+    global_slots: Vec<HashMap<String, usize>>,
+    imported: Vec<bool>,
 
     // Invocation fields
-    native_functions: HashMap<String, NativeFunction>,
+    // indexed like `NATIVE_FUNCTIONS`.
+    native_functions: Vec<NativeFunction>,
     chunks: Vec<Rc<Chunk>>,
     last_program_counts: Vec<usize>,
     last_stack_offsets: Vec<usize>,
+    last_modules: Vec<usize>,
     next_stack_offsets: Vec<usize>,
+    // length of next_stack_offsets when each frame is entered, restored on exceptions.
+    invoke_depths: Vec<usize>,
     return_value: Value,
 
+    // Exception fields
+    thrown: Option<Value>,
+
     // Timer
     started: Instant,
 
@@ -40,26 +52,63 @@ pub struct VirtualMachine {
 
 impl VirtualMachine {
     pub fn new(bytecode: Bytecode) -> Self {
-        let chunk = Rc::clone(&bytecode.script);
+        let chunk = Rc::clone(&bytecode.modules[0].script);
         let exit_program_count = chunk.len();
-        let mut native_functions: HashMap<String, NativeFunction> = HashMap::new();
-        native_functions.insert(String::from("clock"), native_clock);
+        let mut imported = vec![false; bytecode.modules.len()];
+        imported[0] = true;
+        #[cfg(feature = "stack-monitor")]
+        let call_stack = vec![bytecode.modules[0].name.clone()];
+        let native_functions = NATIVE_FUNCTIONS
+            .iter()
+            .map(|(name, _)| match *name {
+                "clock" => native_clock as NativeFunction,
+                _ => unimplemented!("native function {name}"),
+            })
+            .collect();
+        let mut heap = Heap::new();
+        let globals = bytecode
+            .modules
+            .iter()
+            .map(|module| {
+                let functions = &module.functions;
+                module
+                    .globals
+                    .iter()
+                    .map(|name| Some(Value::from(heap.spawn_function(functions.get(name)?))))
+                    .collect()
+            })
+            .collect();
+        let global_slots = bytecode
+            .modules
+            .iter()
+            .map(|module| {
+                (0..module.globals.len())
+                    .map(|slot| (module.globals[slot].clone(), slot))
+                    .collect()
+            })
+            .collect();
         Self {
-            bytecode,
             program_count: 0,
             stack_offset: 0,
             stack: Stack::new(),
-            heap: Heap::new(),
-            globals: HashMap::new(),
+            heap,
+            module: 0,
+            globals,
+            global_slots,
+            imported,
             native_functions,
             chunks: vec![chunk],
             last_program_counts: vec![exit_program_count],
             last_stack_offsets: vec![0],
+            last_modules: vec![0],
             next_stack_offsets: Vec::new(),
-            return_value: Value::Nil,
+            invoke_depths: vec![0],
+            return_value: Value::nil(),
+            thrown: None,
             started: Instant::now(),
+            bytecode,
             #[cfg(feature = "stack-monitor")]
-            call_stack: vec!["<script>".into()],
+            call_stack,
         }
     }
 
@@ -67,174 +116,271 @@ impl VirtualMachine {
         #[cfg(feature = "stack-monitor")]
         println!("━━━━━━━ Stack Monitor ━━━━━━━");
 
-        while !self.chunks.is_empty() {
-            while self.program_count < self.current_chunk().len() {
-                let instruction = self.current_chunk()[self.program_count].clone();
-                let span = self.current_chunk().span(self.program_count).clone();
+        // runtime errors are catchable, execution resumes from the handler.
+        while let Err(diagnostic) = self.execute() {
+            self.catch(diagnostic)?;
+        }
 
+        #[cfg(feature = "stack-monitor")]
+        println!();
+
+        Ok(())
+    }
+
+    fn execute(&mut self) -> DiagnosableResult {
+        // the chunk of the current frame is cached, until a frame is entered or left.
+        'frames: while let Some(chunk) = self.chunks.last().cloned() {
+            while self.program_count < chunk.len() {
+                let program_count = self.program_count;
+                let (opcode, operand, next) = chunk.fetch(program_count);
+                // spans are only looked up once an error is raised.
+                let span = || chunk.span(program_count).clone();
+
+                // arithmetic and relational operators, which take numbers only.
                 macro_rules! binary {
-                    ($variant: ident, $operator: tt) => {{
-                        let right = self.stack.pop(span.clone())?;
-                        let left = self.stack.pop(span.clone())?;
-                        if let (Value::Number(left), Value::Number(right)) = (left, right) {
-                            self.stack.push(Value::$variant(left $operator right), span)?;
-                        } else {
-                            raise!("E0008", span)
+                    ($operator: tt) => {{
+                        let right = self.stack.pop(span)?;
+                        let left = self.stack.pop(span)?;
+                        match (left.as_number(), right.as_number()) {
+                            (Some(left), Some(right)) => {
+                                self.stack.push(Value::from(left $operator right), span)?;
+                            }
+                            _ => raise!("E0008", span()),
                         }
                     }};
-
-                    (arithmetic $operator: tt) => { binary!(Number, $operator) };
-                    (relational $operator: tt) => { binary!(Boolean, $operator) };
                 }
 
                 #[cfg(feature = "stack-monitor")]
                 {
                     let function_name = self.call_stack.last().unwrap();
+                    let (instruction, _) = chunk.instruction(program_count);
                     println!(
                         "{function_name}::{:04} {:?}",
                         self.program_count, instruction
                     );
                 }
 
-                match instruction {
-                    Instruction::LoadConstant(index) => {
-                        let constant = self.current_chunk().constant(index).clone();
-                        match constant {
-                            Constant::Number(number) => {
-                                self.stack.push(Value::Number(number), span)?
-                            }
+                match opcode {
+                    Opcode::LoadConstant | Opcode::LoadConstantLong => {
+                        let value = match chunk.constant(operand as usize) {
+                            Constant::Number(number) => Value::from(*number),
                             Constant::String(string) => {
-                                let reference = self.heap.spawn_string(string);
-                                self.stack.push(Value::String(reference), span)?;
+                                Value::from(self.heap.spawn_string(string.clone()))
                             }
-                        }
+                            Constant::Function(function) => {
+                                Value::from(self.heap.spawn_function(function))
+                            }
+                        };
+                        self.stack.push(value, span)?;
                     }
-                    Instruction::Add => {
-                        let right = self.stack.pop(span.clone())?;
-                        let left = self.stack.pop(span.clone())?;
-                        match (left, right) {
+                    Opcode::Add => {
+                        let right = self.stack.pop(span)?;
+                        let left = self.stack.pop(span)?;
+                        let value = match (left.as_number(), right.as_number()) {
                             // arithmetic addition
-                            (Value::Number(left), Value::Number(right)) => {
-                                self.stack.push(Value::Number(left + right), span)?;
-                            }
+                            (Some(left), Some(right)) => Value::from(left + right),
                             // string concatenation
-                            (Value::String(this), Value::String(that)) => {
-                                let reference = self.heap.spawn_string(format!(
-                                    "{}{}",
-                                    this.deref(),
-                                    that.deref()
-                                ));
-                                self.stack.push(Value::String(reference), span)?;
-                            }
-                            _ => raise!("E0009", span),
-                        }
+                            _ => match (left.as_string(), right.as_string()) {
+                                (Some(this), Some(that)) => {
+                                    Value::from(self.heap.spawn_string(format!(
+                                        "{}{}",
+                                        this.deref(),
+                                        that.deref()
+                                    )))
+                                }
+                                _ => raise!("E0009", span()),
+                            },
+                        };
+                        self.stack.push(value, span)?;
                     }
-                    Instruction::Subtract => binary!(arithmetic -),
-                    Instruction::Multiply => binary!(arithmetic *),
-                    Instruction::Divide => binary!(arithmetic /),
-                    Instruction::Negate => {
-                        if let Value::Number(number) = self.stack.pop(span.clone())? {
-                            self.stack.push(Value::Number(-number), span)?;
-                        } else {
-                            raise!("E0008", span);
-                        }
+                    Opcode::Subtract => binary!(-),
+                    Opcode::Multiply => binary!(*),
+                    Opcode::Divide => binary!(/),
+                    Opcode::Modulo => binary!(%),
+                    Opcode::Negate => match self.stack.pop(span)?.as_number() {
+                        Some(number) => self.stack.push(Value::from(-number), span)?,
+                        None => raise!("E0008", span()),
+                    },
+                    Opcode::Not => {
+                        let value = self.stack.pop(span)?.is_truthy();
+                        self.stack.push(Value::from(!value), span)?;
                     }
-                    Instruction::Not => {
-                        let value: bool = self.stack.pop(span.clone())?.boolean();
-                        self.stack.push(Value::Boolean(!value), span)?;
+                    Opcode::Greater => binary!(>),
+                    Opcode::Less => binary!(<),
+                    Opcode::GreaterEqual => binary!(>=),
+                    Opcode::LessEqual => binary!(<=),
+                    Opcode::Equal => {
+                        let right = self.stack.pop(span)?;
+                        let left = self.stack.pop(span)?;
+                        self.stack.push(Value::from(left == right), span)?;
                     }
-                    Instruction::Greater => binary!(relational >),
-                    Instruction::Less => binary!(relational <),
-                    Instruction::Equal => {
-                        let right = self.stack.pop(span.clone())?;
-                        let left = self.stack.pop(span.clone())?;
-                        self.stack.push(Value::Boolean(left == right), span)?;
+                    Opcode::NotEqual => {
+                        let right = self.stack.pop(span)?;
+                        let left = self.stack.pop(span)?;
+                        self.stack.push(Value::from(left != right), span)?;
                     }
-                    Instruction::True => self.stack.push(Value::Boolean(true), span)?,
-                    Instruction::False => self.stack.push(Value::Boolean(false), span)?,
-                    Instruction::Nil => self.stack.push(Value::Nil, span)?,
-                    Instruction::Print => {
+                    Opcode::True => self.stack.push(Value::from(true), span)?,
+                    Opcode::False => self.stack.push(Value::from(false), span)?,
+                    Opcode::Nil => self.stack.push(Value::nil(), span)?,
+                    Opcode::Print => {
                         let value = self.stack.pop(span)?;
                         println!("{value}");
                     }
-                    Instruction::Pop => {
+                    Opcode::Pop => {
                         self.stack.pop(span)?;
                     }
-                    Instruction::DefineGlobal => {
-                        let name = self.pop_identifier(span.clone())?;
-                        let value = self.stack.pop(span.clone())?;
-                        if self.globals.contains_key(name.deref()) {
-                            raise!("E0011", span);
+                    Opcode::Duplicate => {
+                        let value = self.stack.top(span)?;
+                        self.stack.push(value, span)?;
+                    }
+                    Opcode::DefineGlobal | Opcode::DefineGlobalLong => {
+                        let value = self.stack.pop(span)?;
+                        let global = &mut self.globals[self.module][operand as usize];
+                        if global.is_some() {
+                            raise!("E0011", span());
                         }
-                        self.globals.insert(name.deref().clone(), value);
+                        *global = Some(value);
                     }
-                    Instruction::GetGlobal => {
-                        let name = self.pop_identifier(span.clone())?;
-                        self.stack
-                            .push(self.global_ref(name, span.clone())?.clone(), span)?;
+                    Opcode::GetGlobal | Opcode::GetGlobalLong => {
+                        let value = match &self.globals[self.module][operand as usize] {
+                            Some(value) => value.clone(),
+                            None => raise!("E0012", span()),
+                        };
+                        self.stack.push(value, span)?;
                     }
-                    Instruction::SetGlobal => {
-                        let name = self.pop_identifier(span.clone())?;
-                        let value = self.stack.top(span.clone())?.clone();
-                        *self.global_mut(name, span)? = value;
+                    Opcode::SetGlobal | Opcode::SetGlobalLong => {
+                        let value = self.stack.top(span)?;
+                        match &mut self.globals[self.module][operand as usize] {
+                            Some(global) => *global = value,
+                            None => raise!("E0012", span()),
+                        }
+                    }
+                    Opcode::GetLocal | Opcode::GetLocalLong => {
+                        let slot = self.stack_offset + operand as usize;
+                        self.stack.push(self.stack.get(slot), span)?;
                     }
-                    Instruction::GetLocal(index) => {
-                        self.stack
-                            .push(self.stack[self.stack_offset + index].clone(), span)?;
+                    Opcode::SetLocal | Opcode::SetLocalLong => {
+                        let value = self.stack.top(span)?;
+                        self.stack.set(self.stack_offset + operand as usize, value);
                     }
-                    Instruction::SetLocal(index) => {
-                        let value = self.stack.top(span)?.clone();
-                        self.stack[self.stack_offset + index] = value;
+                    Opcode::JumpIfFalse | Opcode::JumpIfFalseLong => {
+                        let condition = self.stack.top(span)?.is_truthy();
+                        if !condition {
+                            self.program_count = (self.program_count as isize + operand) as usize;
+                            continue;
+                        }
                     }
-                    Instruction::JumpIfFalse(offset) => {
-                        let condition: bool = self.stack.top(span)?.boolean();
+                    Opcode::PopJumpIfFalse | Opcode::PopJumpIfFalseLong => {
+                        let condition = self.stack.pop(span)?.is_truthy();
                         if !condition {
-                            self.program_count = (self.program_count as isize + offset) as usize;
+                            self.program_count = (self.program_count as isize + operand) as usize;
                             continue;
                         }
                     }
-                    Instruction::Jump(offset) => {
-                        self.program_count = (self.program_count as isize + offset) as usize;
+                    Opcode::Jump | Opcode::JumpLong => {
+                        self.program_count = (self.program_count as isize + operand) as usize;
+                        continue;
+                    }
+                    Opcode::PrepareInvoke => self.next_stack_offsets.push(self.stack.len()),
+                    Opcode::InvokeNative => {
+                        self.next_stack_offsets.pop(); // Native functions don't need stack frames.
+                        let value = self.native_functions[operand as usize](self);
+                        self.stack.push(value, span)?;
+                    }
+                    Opcode::Invoke | Opcode::InvokeGlobal | Opcode::InvokeGlobalLong => {
+                        let function = match opcode {
+                            Opcode::Invoke => self.stack.pop(span)?,
+                            // functions invoked by slots are function values in globals.
+                            _ => match &self.globals[self.module][operand as usize] {
+                                Some(value) => value.clone(),
+                                None => raise!("E0015", span()),
+                            },
+                        };
+                        let function = match function.as_function() {
+                            Some(function) => Rc::clone(&function),
+                            None => raise!("E0014", span()),
+                        };
+
+                        // check arguments before any frame changes, in case it's caught.
+                        let argument_count =
+                            self.stack.len() - self.next_stack_offsets.last().unwrap();
+                        if argument_count != function.arity {
+                            let diagnostic = errors::raise("E0016", span())
+                                .with_labels(vec![errors::secondary(
+                                    function.span.clone(),
+                                    "function defined here",
+                                )])
+                                .with_notes(vec![format!(
+                                    "expected {} arguments, found {}",
+                                    function.arity, argument_count
+                                )]);
+                            return Err(Box::new(diagnostic));
+                        }
+
+                        #[cfg(feature = "stack-monitor")]
+                        self.call_stack.push(function.name.clone());
+
+                        self.last_stack_offsets.push(self.stack_offset);
+                        self.stack_offset = self.next_stack_offsets.pop().unwrap();
+                        self.last_modules.push(self.module);
+                        self.module = function.module;
+                        self.invoke_depths.push(self.next_stack_offsets.len());
+                        self.last_program_counts.push(next);
+                        self.program_count = 0;
+                        self.chunks.push(Rc::clone(&function.chunk));
+                        self.return_value = Value::nil();
+                        continue 'frames;
+                    }
+                    Opcode::Return => {
+                        self.return_value = self.stack.pop(span)?;
+                        break;
+                    }
+                    Opcode::Throw => {
+                        let value = self.stack.pop(span)?;
+                        self.thrown = Some(value.clone());
+                        // caught runtime errors are thrown again as they were.
+                        if let Some(diagnostic) = value.as_error() {
+                            return Err(Box::new(diagnostic.deref().clone()));
+                        }
+                        raise!("E0018", span(), format!("thrown value: {value}"))
                     }
-                    Instruction::PrepareInvoke => self.next_stack_offsets.push(self.stack.len()),
-                    Instruction::Invoke => {
-                        let name = self.pop_identifier(span.clone())?;
-                        if let Some(native_function) = self.native_functions.get(name.deref()) {
-                            self.next_stack_offsets.pop(); // Native functions don't need stack frames.
-                            let value = (*native_function)(self);
-                            self.stack.push(value, span)?;
-                        } else {
-                            let function = match self.bytecode.functions.get(name.deref()) {
-                                Some(function) => function,
-                                None => raise!("E0015", span),
-                            };
+                    Opcode::Import | Opcode::ImportLong => {
+                        let module = operand as usize;
+                        // modules are executed only once, at the first time imported.
+                        if !self.imported[module] {
+                            self.imported[module] = true;
+                            let script = Rc::clone(&self.bytecode.modules[module].script);
 
                             #[cfg(feature = "stack-monitor")]
-                            self.call_stack.push(name.deref().clone());
+                            self.call_stack
+                                .push(self.bytecode.modules[module].name.clone());
 
                             self.last_stack_offsets.push(self.stack_offset);
-                            self.stack_offset = self.next_stack_offsets.pop().unwrap();
-
-                            let argument_count = self.stack.len() - self.stack_offset;
-                            if argument_count != function.arity {
-                                raise! {
-                                    "E0016", span,
-                                    format!(
-                                        "expected {} arguments, found {}",
-                                        function.arity, argument_count
-                                    )
-                                }
-                            }
-                            self.last_program_counts.push(self.program_count + 1);
+                            self.stack_offset = self.stack.len();
+                            self.last_modules.push(self.module);
+                            self.module = module;
+                            self.invoke_depths.push(self.next_stack_offsets.len());
+                            self.last_program_counts.push(next);
                             self.program_count = 0;
-                            self.chunks.push(Rc::clone(&function.chunk));
-                            self.return_value = Value::Nil;
-                            continue;
+                            self.chunks.push(script);
+                            self.return_value = Value::nil();
+                            continue 'frames;
                         }
+                        self.stack.push(Value::module(module), span)?;
                     }
-                    Instruction::Return => {
-                        self.return_value = self.stack.pop(span)?;
-                        break;
+                    Opcode::GetProperty | Opcode::GetPropertyLong => {
+                        let name = match chunk.constant(operand as usize) {
+                            Constant::String(name) => name,
+                            _ => raise!("E0010", span()),
+                        };
+                        let Some(module) = self.stack.pop(span)?.as_module() else {
+                            raise!("E0021", span());
+                        };
+                        let value = match self.global(module, name) {
+                            Some(value) => value.clone(),
+                            None => raise!("E0012", span()),
+                        };
+                        self.stack.push(value, span)?;
                     }
                 }
 
@@ -243,52 +389,70 @@ impl VirtualMachine {
                     println!("{:?}", self.stack);
                 }
 
-                self.program_count += 1;
+                self.program_count = next;
             }
 
             while self.stack.len() > self.stack_offset {
                 self.stack.try_pop().unwrap();
             }
             self.stack_offset = self.last_stack_offsets.pop().unwrap();
+            self.module = self.last_modules.pop().unwrap();
             self.stack
-                .try_push(mem::replace(&mut self.return_value, Value::Nil));
+                .try_push(mem::replace(&mut self.return_value, Value::nil()));
             let last_program_count = self.last_program_counts.pop().unwrap();
             self.program_count = last_program_count;
             self.chunks.pop().unwrap();
-        }
-
-        #[cfg(feature = "stack-monitor")]
-        println!();
+            self.invoke_depths.pop().unwrap();
 
+            #[cfg(feature = "stack-monitor")]
+            self.call_stack.pop();
+        }
         Ok(())
     }
 
-    fn current_chunk(&self) -> &Chunk {
-        self.chunks.last().unwrap()
-    }
-
-    fn pop_identifier(&mut self, span: Span) -> DiagnosableResult<Reference<String>> {
-        match self.stack.pop(span.clone())? {
-            Value::String(identifier) => Ok(identifier),
-            _ => raise!("E0010", span),
-        }
-    }
+    // Find the handler of the exception, unwinding call frames until it's found. The
+    // diagnostic is returned if the exception is never caught.
+    fn catch(&mut self, diagnostic: Box<Diagnostic>) -> DiagnosableResult {
+        let exception = match self.thrown.take() {
+            Some(value) => value,
+            None => Value::from(self.heap.spawn(diagnostic.deref().clone())),
+        };
+        let mut program_count = self.program_count;
+        while let Some(chunk) = self.chunks.last() {
+            if let Some(handler) = chunk.handler(program_count) {
+                let target = handler.target;
+                while self.stack.len() > self.stack_offset + handler.depth {
+                    self.stack.try_pop().unwrap();
+                }
+                self.next_stack_offsets
+                    .truncate(*self.invoke_depths.last().unwrap());
+                self.stack.try_push(exception);
+                self.program_count = target;
+                return Ok(());
+            }
+            while self.stack.len() > self.stack_offset {
+                self.stack.try_pop().unwrap();
+            }
+            self.stack_offset = self.last_stack_offsets.pop().unwrap();
+            self.module = self.last_modules.pop().unwrap();
+            // return addresses are right after invocations, so the byte before is in them.
+            program_count = self.last_program_counts.pop().unwrap().wrapping_sub(1);
+            self.chunks.pop().unwrap();
+            self.invoke_depths.pop().unwrap();
 
-    fn global_ref(&self, name: impl AsRef<str>, span: Span) -> DiagnosableResult<&Value> {
-        if let Some(value) = self.globals.get(name.as_ref()) {
-            return Ok(value);
+            #[cfg(feature = "stack-monitor")]
+            self.call_stack.pop();
         }
-        raise!("E0012", span);
+        Err(diagnostic)
     }
 
-    fn global_mut(&mut self, name: impl AsRef<str>, span: Span) -> DiagnosableResult<&mut Value> {
-        if let Some(value) = self.globals.get_mut(name.as_ref()) {
-            return Ok(value);
-        }
-        raise!("E0012", span);
+    // defined global of a module by name.
+    fn global(&self, module: usize, name: &str) -> Option<&Value> {
+        let slot = *self.global_slots[module].get(name)?;
+        self.globals[module][slot].as_ref()
     }
 }
 
 fn native_clock(vm: &mut VirtualMachine) -> Value {
-    Value::Number(vm.started.elapsed().as_millis() as f64)
+    Value::from(vm.started.elapsed().as_millis() as f64)
 }
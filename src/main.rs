@@ -1,14 +1,14 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use std::fmt::Display;
-use std::io;
+use std::path::Path;
+use std::{env, fs, io, process};
 use std::io::Write;
-use std::ops::Deref;
 
 use mimalloc::MiMalloc;
 
-use rlox_analyzer::{compiler, parser, scanner};
+use rlox_analyzer::linter::{Level, Lints};
+use rlox_analyzer::loader;
 use rlox_intermediate::*;
 use rlox_runtime::VirtualMachine;
 
@@ -16,6 +16,75 @@ use rlox_runtime::VirtualMachine;
 static ALLOCATOR: MiMalloc = MiMalloc;
 
 fn main() {
+    let mut lints = Lints::new();
+    let mut positionals = Vec::new();
+    let mut output = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // `-A`, `-W` and `-D` allow, warn or deny a warning code, or all of them by
+        // `warnings`, e.g. `-D warnings -A W0001`.
+        let level = match arg.as_str() {
+            "--explain" => explain(args.next()),
+            "-A" => Level::Allow,
+            "-W" => Level::Warn,
+            "-D" => Level::Deny,
+            "-o" => {
+                output = Some(args.next().unwrap_or_else(|| {
+                    eprintln!("expected an output path after -o");
+                    process::exit(1);
+                }));
+                continue;
+            }
+            _ => {
+                positionals.push(arg);
+                continue;
+            }
+        };
+        match args.next() {
+            Some(code) if code == "warnings" => lints.set_all(level),
+            Some(code) if errors::is_warning(&code) => lints.set(code, level),
+            code => {
+                eprintln!("expected a warning code after {arg}, found {code:?}");
+                process::exit(1);
+            }
+        }
+    }
+    // `rlox compile foo.lox -o foo.loxc` compiles a script into a bytecode file, which is
+    // run by `rlox run foo.loxc`. Both scripts and bytecode files are run by `rlox <path>`.
+    //
+    // `rlox disassemble <path>` prints the assembly of a script or bytecode file, and
+    // `rlox assemble foo.loxa -o foo.loxc` assembles it back into a bytecode file.
+    match positionals.as_slice() {
+        [command, path] if command == "compile" => compile_file(path, output, &lints),
+        [command, path] if command == "run" => run_file(path, &lints),
+        [command, path] if command == "disassemble" => disassemble_file(path, &lints),
+        [command, path] if command == "assemble" => assemble_file(path, output),
+        [path] => run_file(path, &lints),
+        [] => repl(&lints),
+        _ => {
+            eprintln!(
+                "usage: rlox [compile <path> [-o <output>] | run <path> | disassemble <path> \
+                 | assemble <path> [-o <output>] | <path>]"
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn explain(code: Option<String>) -> ! {
+    match code.as_deref().and_then(errors::explain) {
+        Some(details) => {
+            print!("{details}");
+            process::exit(0);
+        }
+        None => {
+            eprintln!("expected an error or warning code after --explain, found {code:?}");
+            process::exit(1);
+        }
+    }
+}
+
+fn repl(lints: &Lints) {
     let mut buffer = String::new();
     loop {
         print!(">> ");
@@ -24,21 +93,168 @@ fn main() {
         if buffer.trim().is_empty() {
             break;
         }
-        let mut source = DiagnosableSource::new("<script>", &buffer);
-        if let Err(diagnostic) = diagnosable_main(&source) {
-            source.diagnose(&diagnostic);
+        let mut sources = SourceMap::new();
+        let file = sources.add("<script>", buffer.as_str());
+        if let Err(diagnostics) = diagnosable_main(&mut sources, file, None, lints) {
+            report(&sources, &diagnostics);
         }
         buffer.clear();
     }
 }
 
-fn diagnosable_main<N, S>(source: &DiagnosableSource<N, S>) -> DiagnosableResult
-where
-    N: Display + Clone,
-    S: AsRef<str>,
-{
-    let tokens = scanner::scan(source.deref())?;
-    let declarations = parser::parse(tokens)?;
-    let bytecode = compiler::compile(declarations)?;
-    VirtualMachine::new(bytecode).run()
+fn read_file(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|error| {
+        eprintln!("cannot read {path}: {error}");
+        process::exit(1);
+    })
+}
+
+fn read_source(path: &str, buffer: Vec<u8>) -> String {
+    String::from_utf8(buffer).unwrap_or_else(|error| {
+        eprintln!("cannot read {path}: {error}");
+        process::exit(1);
+    })
+}
+
+fn run_file(path: &str, lints: &Lints) {
+    let buffer = read_file(path);
+    if Bytecode::is_serialized(&buffer) {
+        return run_bytecode(path, &buffer);
+    }
+    let mut sources = SourceMap::new();
+    let file = sources.add(path, read_source(path, buffer));
+    if is_assembly(path) {
+        let bytecode = assemble(&sources, file);
+        if let Err(diagnostic) = VirtualMachine::new(bytecode).run() {
+            report(&sources, &[*diagnostic]);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Err(diagnostics) = diagnosable_main(&mut sources, file, Some(Path::new(path)), lints) {
+        report(&sources, &diagnostics);
+        process::exit(1);
+    }
+}
+
+// Source files are embedded in the bytecode file, which runtime errors are reported against.
+fn run_bytecode(path: &str, buffer: &[u8]) {
+    let (bytecode, sources) = Bytecode::deserialize(buffer).unwrap_or_else(|error| {
+        eprintln!("cannot load {path}: {error}");
+        process::exit(1);
+    });
+    if let Err(diagnostic) = VirtualMachine::new(bytecode).run() {
+        report(&sources, &[*diagnostic]);
+        process::exit(1);
+    }
+}
+
+fn compile_file(path: &str, output: Option<String>, lints: &Lints) {
+    let source = read_source(path, read_file(path));
+    let mut sources = SourceMap::new();
+    let file = sources.add(path, source);
+    let mut warnings = Vec::new();
+    let bytecode = loader::load(
+        &mut sources,
+        file,
+        Some(Path::new(path)),
+        lints,
+        &mut warnings,
+    );
+    report(&sources, &warnings);
+    let bytecode = bytecode.unwrap_or_else(|diagnostics| {
+        report(&sources, &diagnostics);
+        process::exit(1);
+    });
+    let output = output.unwrap_or_else(|| {
+        let output = Path::new(path).with_extension("loxc");
+        output.display().to_string()
+    });
+    write_bytecode(&bytecode, &sources, &output);
+}
+
+fn is_assembly(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "loxa")
+}
+
+// Assembly is verified like bytecode files, since it's written by hand.
+fn assemble(sources: &SourceMap, file: FileId) -> Bytecode {
+    let name = sources.name(file);
+    let bytecode = Bytecode::assemble(sources.source(file), file).unwrap_or_else(|error| {
+        eprintln!("cannot assemble {name}: {error}");
+        process::exit(1);
+    });
+    if let Err(error) = bytecode.verify() {
+        eprintln!("cannot assemble {name}: {error}");
+        process::exit(1);
+    }
+    bytecode
+}
+
+fn write_bytecode(bytecode: &Bytecode, sources: &SourceMap, output: &str) {
+    if let Err(error) = fs::write(output, bytecode.serialize(sources)) {
+        eprintln!("cannot write {output}: {error}");
+        process::exit(1);
+    }
+}
+
+// Scripts are compiled first, while bytecode files are disassembled as they are.
+fn disassemble_file(path: &str, lints: &Lints) {
+    let buffer = read_file(path);
+    if Bytecode::is_serialized(&buffer) {
+        let (bytecode, sources) = Bytecode::deserialize(&buffer).unwrap_or_else(|error| {
+            eprintln!("cannot load {path}: {error}");
+            process::exit(1);
+        });
+        return print!("{}", bytecode.disassemble(&sources));
+    }
+    let mut sources = SourceMap::new();
+    let file = sources.add(path, read_source(path, buffer));
+    let mut warnings = Vec::new();
+    let bytecode = loader::load(
+        &mut sources,
+        file,
+        Some(Path::new(path)),
+        lints,
+        &mut warnings,
+    );
+    report(&sources, &warnings);
+    let bytecode = bytecode.unwrap_or_else(|diagnostics| {
+        report(&sources, &diagnostics);
+        process::exit(1);
+    });
+    print!("{}", bytecode.disassemble(&sources));
+}
+
+fn assemble_file(path: &str, output: Option<String>) {
+    let mut sources = SourceMap::new();
+    let file = sources.add(path, read_source(path, read_file(path)));
+    let bytecode = assemble(&sources, file);
+    let output = output.unwrap_or_else(|| {
+        let output = Path::new(path).with_extension("loxc");
+        output.display().to_string()
+    });
+    write_bytecode(&bytecode, &sources, &output);
+}
+
+fn diagnosable_main(
+    sources: &mut SourceMap,
+    file: FileId,
+    path: Option<&Path>,
+    lints: &Lints,
+) -> DiagnosticsResult {
+    let mut warnings = Vec::new();
+    let bytecode = loader::load(sources, file, path, lints, &mut warnings);
+    report(sources, &warnings);
+    let bytecode = bytecode?;
+    VirtualMachine::new(bytecode).run().map_err(|d| vec![*d])
+}
+
+fn report(sources: &SourceMap, diagnostics: &[Diagnostic]) {
+    let reporter = Reporter::new();
+    for diagnostic in diagnostics {
+        reporter.report(sources, diagnostic);
+    }
 }
@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::Path;
+
+use rlox_analyzer::linter::Lints;
+use rlox_analyzer::loader;
+use rlox_intermediate::*;
+use rlox_runtime::VirtualMachine;
+
+// Run a script of the suite, which throws at the first failed check. The script is verified
+// and run again after a round trip through the bytecode file format, and through assembly.
+fn run(path: &Path) -> DiagnosticsResult {
+    let mut sources = SourceMap::new();
+    let file = sources.add(
+        path.display().to_string(),
+        fs::read_to_string(path).unwrap(),
+    );
+    // assembly is hand-written for instructions never emitted by the compiler.
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "loxa")
+    {
+        let bytecode = Bytecode::assemble(sources.source(file), file).unwrap();
+        bytecode.verify().unwrap();
+        return VirtualMachine::new(bytecode).run().map_err(|d| vec![*d]);
+    }
+    let bytecode = loader::load(
+        &mut sources,
+        file,
+        Some(path),
+        &Lints::new(),
+        &mut Vec::new(),
+    )?;
+    bytecode.verify().unwrap();
+    let serialized = bytecode.serialize(&sources);
+    // without sources, the assembly has no source line annotations to differ.
+    let assembly = bytecode.disassemble(&SourceMap::new());
+    VirtualMachine::new(bytecode).run().map_err(|d| vec![*d])?;
+
+    let (bytecode, _) = Bytecode::deserialize(&serialized).unwrap();
+    VirtualMachine::new(bytecode).run().map_err(|d| vec![*d])?;
+
+    let bytecode = Bytecode::assemble(&assembly, file).unwrap();
+    assert_eq!(bytecode.disassemble(&SourceMap::new()), assembly);
+    VirtualMachine::new(bytecode).run().map_err(|d| vec![*d])
+}
+
+#[test]
+fn conformance() {
+    let suite = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    // directories hold modules imported by scripts, rather than scripts of the suite.
+    let mut paths = fs::read_dir(suite)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no script is found in the suite");
+
+    for path in paths {
+        if let Err(diagnostics) = run(&path) {
+            let failures = diagnostics
+                .iter()
+                .map(|diagnostic| format!("{}: {:?}", diagnostic.message, diagnostic.notes))
+                .collect::<Vec<_>>();
+            panic!("{} failed: {failures:?}", path.display());
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::Path;
+
+use rlox_analyzer::linter::{Level, Lints};
+use rlox_analyzer::loader;
+use rlox_intermediate::*;
+use rlox_runtime::VirtualMachine;
+
+// A diagnostic by its severity, code, line and the source text of its primary label.
+type Expectation = (String, String, usize, String);
+
+// Diagnostics annotated in comments of the script, e.g. `a.b++; // error[E0013]: `++``,
+// where the quoted text is spanned by the primary label on the same line.
+fn expected(source: &str) -> Vec<Expectation> {
+    let mut expected = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let Some((_, comment)) = line.split_once("//") else {
+            continue;
+        };
+        for annotation in comment.split(" // ") {
+            let annotation = annotation.trim();
+            let Some((severity, rest)) = annotation.split_once('[') else {
+                continue;
+            };
+            if severity != "error" && severity != "warning" {
+                continue;
+            }
+            let (code, text) = rest.split_once("]: ").unwrap();
+            let text = text.strip_prefix('`').unwrap().strip_suffix('`').unwrap();
+            expected.push((
+                String::from(severity),
+                String::from(code),
+                index + 1,
+                String::from(text),
+            ));
+        }
+    }
+    expected.sort();
+    expected
+}
+
+fn actual(source: &str, diagnostics: &[Diagnostic]) -> Vec<Expectation> {
+    let mut actual = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let severity = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                severity => panic!("unexpected severity {severity:?}"),
+            };
+            // the primary label comes first, before any secondary ones.
+            let label = &diagnostic.labels[0];
+            let line = source[..label.range.start].matches('\n').count() + 1;
+            (
+                String::from(severity),
+                diagnostic.code.clone().unwrap(),
+                line,
+                String::from(&source[label.range.clone()]),
+            )
+        })
+        .collect::<Vec<_>>();
+    actual.sort();
+    actual
+}
+
+// Sections of the script separated by `// ---` lines, which are run on their own. Other
+// sections are blanked out, so that lines and offsets are kept.
+fn sections(source: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    for (offset, line) in source
+        .match_indices('\n')
+        .map(|(offset, _)| offset + 1)
+        .zip(source.lines())
+    {
+        if line.starts_with("// ---") {
+            sections.push(start..offset);
+            start = offset;
+        }
+    }
+    sections.push(start..source.len());
+    let blank = |text: &str| {
+        text.chars()
+            .map(|c| match c {
+                '\n' => String::from(c),
+                _ => " ".repeat(c.len_utf8()),
+            })
+            .collect::<String>()
+    };
+    sections
+        .into_iter()
+        .map(|range| {
+            blank(&source[..range.start]) + &source[range.clone()] + &blank(&source[range.end..])
+        })
+        .collect()
+}
+
+// Levels of warnings given in `// flags:` lines of the section, like those on the command
+// line, e.g. `// flags: -D warnings -A W0001`.
+fn lints(source: &str) -> Lints {
+    let mut lints = Lints::new();
+    let flags = source
+        .lines()
+        .filter_map(|line| line.strip_prefix("// flags:"))
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>();
+    for pair in flags.chunks(2) {
+        let level = match pair[0] {
+            "-A" => Level::Allow,
+            "-W" => Level::Warn,
+            "-D" => Level::Deny,
+            flag => panic!("unknown flag {flag}"),
+        };
+        match pair[1] {
+            "warnings" => lints.set_all(level),
+            code => lints.set(code, level),
+        }
+    }
+    lints
+}
+
+// Diagnostics of loading the section, and of running it if it's loaded.
+fn run(path: &Path, source: String) -> Vec<Diagnostic> {
+    let lints = lints(&source);
+    let mut sources = SourceMap::new();
+    let file = sources.add(path.display().to_string(), source);
+    let mut diagnostics = Vec::new();
+    match loader::load(
+        &mut sources,
+        file,
+        Some(path),
+        &lints,
+        &mut diagnostics,
+    ) {
+        Ok(bytecode) => {
+            if let Err(diagnostic) = VirtualMachine::new(bytecode).run() {
+                diagnostics.push(*diagnostic);
+            }
+        }
+        Err(errors) => diagnostics.extend(errors),
+    }
+    diagnostics
+}
+
+#[test]
+fn diagnostics() {
+    let suite = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/diagnostics");
+    let mut paths = fs::read_dir(suite)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no script is found in the suite");
+
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        let diagnostics = sections(&source)
+            .into_iter()
+            .flat_map(|section| run(&path, section))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actual(&source, &diagnostics),
+            expected(&source),
+            "{} reports other diagnostics",
+            path.display()
+        );
+    }
+}
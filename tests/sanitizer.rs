@@ -0,0 +1,30 @@
+#![cfg(feature = "gc-sanitizer")]
+
+use std::path::Path;
+use std::process::Command;
+
+// Every object left on the heap is finalized when the VM is dropped, which the sanitizer
+// reports by its kind.
+#[test]
+fn finalizes_every_kind() {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sanitizer/kinds.lox");
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(["-A", "warnings"])
+        .arg(script)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let finalized = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("-- GC finalize: "))
+        .collect::<Vec<_>>();
+
+    let expected = ["\"finalized\"", "<fn answer>", "<Error object: "];
+    for object in expected {
+        assert!(
+            finalized.iter().any(|line| line.starts_with(object)),
+            "{object} is not finalized in {finalized:?}"
+        );
+    }
+}